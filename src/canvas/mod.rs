@@ -1,18 +1,27 @@
 use dashi::utils::*;
 use dashi::*;
+use crate::database::format::ConfigFormat;
+use crate::database::schema::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fs};
 extern crate unzip3;
 use self::unzip3::Unzip3;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CanvasAttachment {
     pub name: String,
+    #[schemars(with = "FormatSchema")]
     pub format: Format,
+    #[schemars(with = "SampleCountSchema")]
     pub samples: SampleCount,
+    #[schemars(with = "LoadOpSchema")]
     pub load_op: LoadOp,
+    #[schemars(with = "StoreOpSchema")]
     pub store_op: StoreOp,
+    #[schemars(with = "LoadOpSchema")]
     pub stencil_load_op: LoadOp,
+    #[schemars(with = "StoreOpSchema")]
     pub stencil_store_op: StoreOp,
     pub clear_color: [f32; 4],
 }
@@ -24,9 +33,10 @@ pub struct CanvasAttachmentDetail {
     pub info: CanvasAttachment,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct CanvasCreateInfo {
     pub name: String,
+    #[schemars(with = "ViewportSchema")]
     pub viewport: Viewport,
     pub color_attachments: Vec<CanvasAttachment>,
     pub depth_stencil: Option<CanvasAttachment>,
@@ -48,11 +58,11 @@ impl Canvas {
         self.viewport
     }
     pub fn render_pass(&self) -> Handle<RenderPass> {
-        return self.render_pass;
+        self.render_pass
     }
 
     pub fn name(&self) -> String {
-        return self.name.clone();
+        self.name.clone()
     }
 
     pub fn color_attachment(&self, idx: u32) -> Handle<ImageView> {
@@ -60,7 +70,7 @@ impl Canvas {
     }
 
     pub fn color_attachment_by_name(&self, name: &str) -> Option<&CanvasAttachmentDetail> {
-        return self.attach_map.get(name);
+        self.attach_map.get(name)
     }
 
     pub fn new(ctx: &mut Context, info: CanvasCreateInfo) -> Self {
@@ -104,13 +114,13 @@ impl Canvas {
                 info: a.clone(),
             });
 
-            return (img, view, attachment);
+            (img, view, attachment)
         };
 
         let colors: Vec<(Handle<Image>, Handle<ImageView>, Attachment)> = info
             .color_attachments
             .into_iter()
-            .map(|a| attach_to_tuple(a))
+            .map(&mut attach_to_tuple)
             .collect();
 
         let (imgs, views, attachs): (Vec<_>, Vec<_>, Vec<_>) = colors.iter().cloned().unzip3();
@@ -142,11 +152,16 @@ impl Canvas {
             color_views: views,
         }
     }
+    /// Reads a canvas description in the format its extension names: `.json`,
+    /// `.ron`, `.toml` or `.yaml`.
     pub fn from_json(ctx: &mut Context, path: &str) -> Self {
-        let json_data = fs::read_to_string(path).expect("Failed to read JSON for Canvas!");
-        let info: CanvasCreateInfo =
-            serde_json::from_str(&json_data).expect("Failed to read Canvas from JSON!");
-
-        return Canvas::new(ctx, info);
+        let format = ConfigFormat::from_path(path);
+        let data = fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Failed to read {} for Canvas!", format.name()));
+        let info: CanvasCreateInfo = format
+            .parse(&data)
+            .unwrap_or_else(|e| panic!("Failed to read Canvas from {}: {}", format.name(), e));
+
+        Canvas::new(ctx, info)
     }
 }
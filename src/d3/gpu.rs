@@ -10,11 +10,11 @@ pub struct MeshMaterialInfo {
 }
 
 impl MeshMaterialInfo {
-    pub fn from(material: &geometry::Material) -> Self {
+    pub fn from(_material: &geometry::Material) -> Self {
         todo!()
     }
 
-    pub fn into_bind_group(&mut self, ctx: &mut dashi::Context) -> BindGroup {
+    pub fn into_bind_group(&mut self, _ctx: &mut dashi::Context) -> BindGroup {
         todo!()
     }
 }
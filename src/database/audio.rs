@@ -0,0 +1,296 @@
+use super::error::*;
+use super::handle::*;
+use super::images::*;
+use super::json::*;
+use super::stats::*;
+use super::vfs::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Vorbis,
+}
+
+impl AudioFormat {
+    /// Format of an audio file, by extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "wav" | "wave" => Some(AudioFormat::Wav),
+            "ogg" | "oga" => Some(AudioFormat::Vorbis),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Length in sample frames, if the header tells it. Ogg streams don't.
+    pub frames: Option<u64>,
+}
+
+/// A loop region in sample frames, `start..end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopPoints {
+    pub start: u64,
+    /// `None` loops at the end of the clip.
+    pub end: Option<u64>,
+}
+
+enum AudioData {
+    /// Interleaved samples in `-1.0..=1.0`.
+    Pcm(Arc<[f32]>),
+    /// The file the clip is decoded from, `path` in the filesystem.
+    Encoded {
+        format: AudioFormat,
+        fs: Arc<dyn FileSystem>,
+        path: String,
+    },
+}
+
+/// A loaded audio entry. Preloaded clips hold decoded PCM; streaming clips only
+/// remember their file and decode it from there through `stream`.
+pub struct AudioClip {
+    pub name: String,
+    pub info: AudioInfo,
+    pub loop_points: Option<LoopPoints>,
+    data: AudioData,
+}
+
+impl AudioClip {
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.data, AudioData::Encoded { .. })
+    }
+
+    /// Every interleaved sample of a preloaded clip. `None` for streaming clips.
+    pub fn samples(&self) -> Option<&[f32]> {
+        match &self.data {
+            AudioData::Pcm(samples) => Some(samples),
+            AudioData::Encoded { .. } => None,
+        }
+    }
+
+    /// A decoder that yields the clip's samples from the start. Streaming clips
+    /// open their file again for every stream.
+    pub fn stream(&self) -> Result<AudioStream, Error> {
+        let (file, source) = match &self.data {
+            AudioData::Pcm(samples) => (String::new(), StreamSource::Pcm(samples.clone(), 0)),
+            AudioData::Encoded { format, fs, path } => {
+                let file = format!("{}/{}", fs.root(), path);
+                let (_, source) =
+                    open_decoder(*format, fs.open(path)?).map_err(|e| audio_error(&self.name, &file, e))?;
+                (file, source)
+            }
+        };
+        Ok(AudioStream {
+            name: self.name.clone(),
+            file,
+            info: self.info,
+            source,
+        })
+    }
+
+    /// Bytes of decoded samples held. Streaming clips hold none.
+    pub fn byte_size(&self) -> usize {
+        match &self.data {
+            AudioData::Pcm(samples) => samples.len() * std::mem::size_of::<f32>(),
+            AudioData::Encoded { .. } => 0,
+        }
+    }
+}
+
+enum StreamSource {
+    Pcm(Arc<[f32]>, usize),
+    Wav(hound::WavReader<Box<dyn ReadSeek>>),
+    Vorbis(Box<lewton::inside_ogg::OggStreamReader<Box<dyn ReadSeek>>>, Vec<f32>),
+}
+
+/// Incremental decoding of an `AudioClip`.
+pub struct AudioStream {
+    name: String,
+    /// The file being decoded, empty for preloaded clips.
+    file: String,
+    info: AudioInfo,
+    source: StreamSource,
+}
+
+impl AudioStream {
+    pub fn info(&self) -> AudioInfo {
+        self.info
+    }
+
+    /// Decodes up to `frames` sample frames, interleaved. Returns an empty buffer
+    /// at the end of the clip.
+    pub fn read(&mut self, frames: usize) -> Result<Vec<f32>, Error> {
+        let wanted = frames * self.info.channels as usize;
+        match &mut self.source {
+            StreamSource::Pcm(samples, pos) => {
+                let end = (*pos + wanted).min(samples.len());
+                let chunk = samples[*pos..end].to_vec();
+                *pos = end;
+                Ok(chunk)
+            }
+            StreamSource::Wav(reader) => {
+                let spec = reader.spec();
+                read_wav_samples(&self.name, &self.file, reader, spec, Some(wanted))
+            }
+            StreamSource::Vorbis(reader, pending) => {
+                while pending.len() < wanted {
+                    match reader.read_dec_packet_itl().map_err(|e| audio_error(&self.name, &self.file, e))? {
+                        Some(packet) => pending.extend(packet.into_iter().map(|s| s as f32 / 32768.0)),
+                        None => break,
+                    }
+                }
+                let end = wanted.min(pending.len());
+                Ok(pending.drain(..end).collect())
+            }
+        }
+    }
+}
+
+fn audio_error(name: &str, file: &str, cause: impl std::fmt::Display) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: name.to_string(),
+        path: file.to_string(),
+        cause: cause.to_string(),
+    })
+}
+
+fn read_wav_samples(
+    name: &str,
+    file: &str,
+    reader: &mut hound::WavReader<Box<dyn ReadSeek>>,
+    spec: hound::WavSpec,
+    limit: Option<usize>,
+) -> Result<Vec<f32>, Error> {
+    let limit = limit.unwrap_or(usize::MAX);
+    match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .take(limit)
+            .collect::<Result<_, _>>()
+            .map_err(|e| audio_error(name, file, e)),
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .take(limit)
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(|e| audio_error(name, file, e))
+        }
+    }
+}
+
+/// Reads the header of an encoded clip and returns a decoder positioned at the
+/// first sample.
+fn open_decoder(format: AudioFormat, file: Box<dyn ReadSeek>) -> Result<(AudioInfo, StreamSource), String> {
+    match format {
+        AudioFormat::Wav => {
+            let reader = hound::WavReader::new(file).map_err(|e| e.to_string())?;
+            let spec = reader.spec();
+            let info = AudioInfo {
+                sample_rate: spec.sample_rate,
+                channels: spec.channels,
+                frames: Some(reader.duration() as u64),
+            };
+            Ok((info, StreamSource::Wav(reader)))
+        }
+        AudioFormat::Vorbis => {
+            let reader = lewton::inside_ogg::OggStreamReader::new(file).map_err(|e| e.to_string())?;
+            let info = AudioInfo {
+                sample_rate: reader.ident_hdr.audio_sample_rate,
+                channels: reader.ident_hdr.audio_channels as u16,
+                frames: None,
+            };
+            Ok((info, StreamSource::Vorbis(Box::new(reader), Vec::new())))
+        }
+    }
+}
+
+/// Reads the header of the audio file `path` without decoding it.
+pub fn probe_audio(fs: &dyn FileSystem, path: &str) -> Result<AudioInfo, String> {
+    let format = AudioFormat::from_path(path).ok_or_else(|| format!("{} is not a .wav or .ogg file", path))?;
+    let file = fs.open(path).map_err(|e| e.to_string())?;
+    open_decoder(format, file).map(|(info, _)| info)
+}
+
+/// Loads the audio file of `cfg`. Preloaded entries are decoded to PCM here;
+/// streaming entries only have their header checked.
+pub fn load_entry_audio(cfg: &AudioJSONEntry, fs: &Arc<dyn FileSystem>) -> Result<AudioClip, Error> {
+    let file = format!("{}/{}", fs.root(), cfg.path);
+    let format = AudioFormat::from_path(&cfg.path)
+        .ok_or_else(|| audio_error(&cfg.name, &file, "not a .wav or .ogg file"))?;
+    let (mut info, source) =
+        open_decoder(format, fs.open(&cfg.path)?).map_err(|e| audio_error(&cfg.name, &file, e))?;
+
+    let data = if cfg.streaming {
+        AudioData::Encoded {
+            format,
+            fs: fs.clone(),
+            path: cfg.path.clone(),
+        }
+    } else {
+        let mut stream = AudioStream {
+            name: cfg.name.clone(),
+            file,
+            info,
+            source,
+        };
+        let samples = match &mut stream.source {
+            StreamSource::Wav(reader) => {
+                let spec = reader.spec();
+                read_wav_samples(&stream.name, &stream.file, reader, spec, None)?
+            }
+            _ => {
+                let mut samples = Vec::new();
+                loop {
+                    let chunk = stream.read(4096)?;
+                    if chunk.is_empty() {
+                        break;
+                    }
+                    samples.extend(chunk);
+                }
+                samples
+            }
+        };
+        info.frames = Some((samples.len() / info.channels.max(1) as usize) as u64);
+        AudioData::Pcm(samples.into())
+    };
+
+    Ok(AudioClip {
+        name: cfg.name.clone(),
+        info,
+        loop_points: cfg.loop_points.map(|l| LoopPoints {
+            start: l.start,
+            end: l.end,
+        }),
+        data,
+    })
+}
+
+pub struct AudioEntry {
+    pub cfg: AudioJSONEntry,
+    pub layer: usize,
+    pub loaded: Option<Arc<AudioClip>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
+}
+
+impl AudioEntry {
+    pub fn load(&mut self, fs: &Arc<dyn FileSystem>) -> Result<(), Error> {
+        let (data, time) = timed(|| load_entry_audio(&self.cfg, fs))?;
+        self.set_loaded(data, time, &**fs);
+        Ok(())
+    }
+}
+
+impl_entry!(AudioEntry, AudioJSONEntry, AudioClip, AssetCategory::Audio, path: path);
+
+pub fn parse_audio(info: AudioJSON, layer: usize) -> Vec<AudioEntry> {
+    parse_entries(info.audio, layer)
+}
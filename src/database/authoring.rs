@@ -0,0 +1,466 @@
+use super::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+fn already_exists(name: &str) -> Error {
+    Error::from(format!("entry {} already exists in database", name))
+}
+
+/// Shaders come from the files below `shader_path`, not from a config.
+fn not_authored(name: &str) -> Error {
+    Error::from(format!("{} is a shader; rename or remove its files instead", name))
+}
+
+/// Custom entries come from configs the database only reads.
+fn custom_not_authored(name: &str) -> Error {
+    Error::from(format!("{} is a custom entry; edit its category config instead", name))
+}
+
+fn lookup_error(name: &str) -> Error {
+    Error::LookupError(LookupError {
+        entry: name.to_string(),
+    })
+}
+
+/// Adds an entry for `cfg`. Nothing changes if the name is taken or its id
+/// collides with another entry.
+fn add_entry<E: DatabaseEntry>(
+    entries: &mut EntryMap<E>,
+    ids: &mut IdTable,
+    cfg: E::Config,
+    layer: usize,
+) -> Result<(), Error> {
+    if entries.contains_key(cfg.name()) {
+        return Err(already_exists(cfg.name()));
+    }
+    let key = AssetKey::new(E::CATEGORY, cfg.name());
+    ids.check_free(&key)?;
+
+    entries
+        .insert(E::from_cfg(cfg, layer))
+        .map_err(|other| Error::from(collision_message(&key, &other)))?;
+    ids.record(key);
+    Ok(())
+}
+
+/// Applies `edit` to a copy of the config of `name` and swaps it in, dropping the
+/// loaded data. Nothing changes if a rename would clash with another entry's name
+/// or id. Returns the layer of the entry.
+fn edit_entry<E: DatabaseEntry>(
+    entries: &mut EntryMap<E>,
+    ids: &mut IdTable,
+    name: &str,
+    edit: impl FnOnce(&mut E::Config),
+) -> Result<usize, Error> {
+    let mut cfg = match entries.get(name) {
+        Some(entry) => entry.cfg().clone(),
+        None => return Err(lookup_error(name)),
+    };
+    edit(&mut cfg);
+
+    let renamed = AssetKey::new(E::CATEGORY, cfg.name());
+    if renamed.name != name {
+        if entries.contains_key(&renamed.name) {
+            return Err(already_exists(&renamed.name));
+        }
+        ids.check_free(&renamed)?;
+    }
+
+    let mut entry = entries.remove(name).unwrap();
+    entry.unload();
+    *entry.cfg_mut() = cfg;
+    let layer = entry.layer();
+    entries
+        .insert(entry)
+        .map_err(|other| Error::from(collision_message(&renamed, &other)))?;
+    ids.remove(&AssetKey::new(E::CATEGORY, name));
+    ids.record(renamed);
+    Ok(layer)
+}
+
+fn remove_entry<E: DatabaseEntry>(entries: &mut EntryMap<E>, name: &str) -> Result<usize, Error> {
+    match entries.remove(name) {
+        Some(entry) => Ok(entry.layer()),
+        None => Err(lookup_error(name)),
+    }
+}
+
+/// Puts back the entry `name` of the highest layer below `layer` that declares
+/// it, once the entry that shadowed it was removed or renamed away.
+fn restore_shadowed<E: DatabaseEntry, J: VersionedConfig>(
+    entries: &mut EntryMap<E>,
+    layers: &[Arc<dyn FileSystem>],
+    infos: &[DatabaseJSON],
+    layer: usize,
+    name: &str,
+    path: fn(&DatabaseJSON) -> &Option<String>,
+    unwrap: fn(J) -> Vec<E::Config>,
+) -> Result<(), Error> {
+    if entries.contains_key(name) {
+        return Ok(());
+    }
+
+    for lower in (0..layer).rev() {
+        let fs = &*layers[lower];
+        let path = match path(&infos[lower]) {
+            Some(path) if fs.exists(path) => path,
+            _ => continue,
+        };
+
+        let current: J = read_versioned(fs, path, &mut Vec::new())?;
+        if let Some(cfg) = unwrap(current).into_iter().find(|cfg| cfg.name() == name) {
+            let key = AssetKey::new(E::CATEGORY, name);
+            entries
+                .insert(E::from_cfg(cfg, lower))
+                .map_err(|other| Error::from(collision_message(&key, &other)))?;
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Configs of every entry `layer` supplies, sorted by name. Entries of the layer's
+/// current config that are overridden by a higher layer are kept as well.
+fn layer_configs<E: DatabaseEntry, J: VersionedConfig>(
+    fs: &dyn FileSystem,
+    entries: &EntryMap<E>,
+    layer: usize,
+    path: &Option<String>,
+    unwrap: fn(J) -> Vec<E::Config>,
+) -> Result<Vec<E::Config>, Error> {
+    let mut configs: Vec<E::Config> = entries
+        .values()
+        .filter(|e| e.layer() == layer)
+        .map(|e| e.cfg().clone())
+        .collect();
+
+    if let Some(path) = path {
+        if fs.exists(path) {
+            let current: J = read_versioned(fs, path, &mut Vec::new())?;
+            configs.extend(unwrap(current).into_iter().filter(|cfg| {
+                entries
+                    .get(cfg.name())
+                    .map(|e| e.layer() > layer)
+                    .unwrap_or(false)
+            }));
+        }
+    }
+
+    configs.sort_by(|a, b| a.name().cmp(b.name()));
+    Ok(configs)
+}
+
+/// Writes `value` to `path` in the format its extension names.
+pub(crate) fn write_config<T: Serialize>(fs: &dyn FileSystem, path: &str, value: &T) -> Result<(), Error> {
+    let text = ConfigFormat::from_path(path)
+        .to_string(value)
+        .map_err(|e| Error::from(format!("{}: {}", path, e)))?;
+    fs.write(path, text.as_bytes())
+}
+
+/// The element of the list `items` that corresponds to element `index` of another
+/// list, `item`: the one with the same `name` if it has one, else the one at the
+/// same index.
+fn counterpart<'a>(items: &'a [serde_json::Value], index: usize, item: &serde_json::Value) -> Option<&'a serde_json::Value> {
+    match item.get("name") {
+        Some(name) => items.iter().find(|i| i.get("name") == Some(name)),
+        None => items.get(index),
+    }
+}
+
+/// Copies the keys of `current` that `known` lacks into `value`. `known` is
+/// `current` as read into the config struct and written back, so the copied keys
+/// are the ones the struct doesn't model. Lists are matched up by entry name.
+fn restore_unmodeled(value: &mut serde_json::Value, current: &serde_json::Value, known: &serde_json::Value) {
+    match (value, current, known) {
+        (serde_json::Value::Object(value), serde_json::Value::Object(current), serde_json::Value::Object(known)) => {
+            for (key, item) in current {
+                match (value.get_mut(key), known.get(key)) {
+                    (Some(value), Some(known)) => restore_unmodeled(value, item, known),
+                    (None, None) => {
+                        value.insert(key.clone(), item.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (serde_json::Value::Array(value), serde_json::Value::Array(current), serde_json::Value::Array(known)) => {
+            for (index, item) in value.iter_mut().enumerate() {
+                if let (Some(current), Some(known)) = (counterpart(current, index, item), counterpart(known, index, item)) {
+                    restore_unmodeled(item, current, known);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Writes `value` over the existing config `path`, keeping the keys its struct
+/// doesn't model. Formats without a value form, and missing or unreadable files,
+/// are written from `value` alone.
+pub(crate) fn update_config<T: Serialize + DeserializeOwned>(
+    fs: &dyn FileSystem,
+    path: &str,
+    value: &T,
+) -> Result<(), Error> {
+    let format = ConfigFormat::from_path(path);
+    if !format.has_value_form() || !fs.exists(path) {
+        return write_config(fs, path, value);
+    }
+
+    let current = format
+        .to_value(&fs.read_to_string(path)?)
+        .map_err(|e| Error::from(format!("{}: {}", path, e)))?;
+    let known = match serde_json::from_value::<T>(current.clone()) {
+        Ok(known) => serde_json::to_value(known)?,
+        Err(_) => return write_config(fs, path, value),
+    };
+
+    let mut updated = serde_json::to_value(value)?;
+    restore_unmodeled(&mut updated, &current, &known);
+    write_config(fs, path, &updated)
+}
+
+/// Writes `value` to the category config `path` of a layer. Layers that don't
+/// declare the category yet get `default` once they have entries for it.
+fn write_category<T: Serialize + DeserializeOwned>(
+    fs: &dyn FileSystem,
+    path: &mut Option<String>,
+    default: &str,
+    has_entries: bool,
+    value: &T,
+) -> Result<(), Error> {
+    if path.is_none() && has_entries {
+        *path = Some(default.to_string());
+    }
+
+    match path {
+        Some(path) => update_config(fs, path, value),
+        None => Ok(()),
+    }
+}
+
+impl Database {
+    /// Layer new entries are added to.
+    fn top_layer(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// Follows up an edit, rename or removal of `name`. Collisions were checked
+    /// before the change, so this only brings the id table up to date. Returns the
+    /// entries whose variant changed.
+    fn entry_changed(&mut self, category: AssetCategory, name: &str, layer: usize) -> Result<Vec<AssetKey>, Error> {
+        let key = AssetKey::new(category, name);
+        self.forget_entry(&key);
+        self.dirty_layers.insert(layer);
+        // A restored lower entry has the same name, so it takes back the id it had.
+        self.restore_shadowed(category, name, layer)?;
+        if self.contains(category, name) {
+            self.ids.record(key);
+        } else {
+            self.ids.remove(&key);
+        }
+        Ok(self.select_variants())
+    }
+
+    /// Restores what a removed or renamed overlay entry `name` of `layer` shadowed.
+    fn restore_shadowed(&mut self, category: AssetCategory, name: &str, layer: usize) -> Result<(), Error> {
+        let (layers, infos) = (&self.layers, &self.infos);
+        match category {
+            AssetCategory::Image => {
+                restore_shadowed(&mut self.images, layers, infos, layer, name, |i| &i.image_cfg, |j: ImageJSON| j.images)
+            }
+            AssetCategory::Atlas => {
+                restore_shadowed(&mut self.atlases, layers, infos, layer, name, |i| &i.atlas_cfg, |j: AtlasJSON| j.atlases)
+            }
+            AssetCategory::Geometry => restore_shadowed(
+                &mut self.geometry,
+                layers,
+                infos,
+                layer,
+                name,
+                |i| &i.geometry_cfg,
+                |j: GeometryJSON| j.models,
+            ),
+            AssetCategory::TTF => {
+                restore_shadowed(&mut self.ttfs, layers, infos, layer, name, |i| &i.ttf_cfg, |j: TTFJSON| j.fonts)
+            }
+            AssetCategory::Particle => restore_shadowed(
+                &mut self.particles,
+                layers,
+                infos,
+                layer,
+                name,
+                |i| &i.particle_cfg,
+                |j: ParticleJSON| j.particles,
+            ),
+            AssetCategory::Audio => {
+                restore_shadowed(&mut self.audio, layers, infos, layer, name, |i| &i.audio_cfg, |j: AudioJSON| j.audio)
+            }
+            AssetCategory::Shader | AssetCategory::Custom => Ok(()),
+        }
+    }
+
+    /// Adds an image entry to the highest layer. Returns the entries whose variant
+    /// changed, see `set_variant_profile`.
+    pub fn add_image(&mut self, cfg: ImageJSONEntry) -> Result<Vec<AssetKey>, Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.images, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(self.select_variants())
+    }
+
+    /// Adds an atlas entry to the highest layer.
+    pub fn add_atlas(&mut self, cfg: AtlasJSONEntry) -> Result<Vec<AssetKey>, Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.atlases, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(self.select_variants())
+    }
+
+    /// Adds a font entry to the highest layer.
+    pub fn add_ttf(&mut self, cfg: TTFJSONEntry) -> Result<Vec<AssetKey>, Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.ttfs, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(self.select_variants())
+    }
+
+    /// Adds a model entry to the highest layer.
+    pub fn add_geometry(&mut self, cfg: GeometryJSONEntry) -> Result<Vec<AssetKey>, Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.geometry, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(self.select_variants())
+    }
+
+    /// Adds a particle system entry to the highest layer.
+    pub fn add_particle_system(&mut self, cfg: ParticleJSONEntry) -> Result<(), Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.particles, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(())
+    }
+
+    /// Adds an audio entry to the highest layer.
+    pub fn add_audio(&mut self, cfg: AudioJSONEntry) -> Result<(), Error> {
+        let layer = self.top_layer();
+        add_entry(&mut self.audio, &mut self.ids, cfg, layer)?;
+        self.dirty_layers.insert(layer);
+        Ok(())
+    }
+
+    /// Edits the config of the image `name`. The entry is unloaded; changing its name
+    /// in `edit` renames it. Returns the entries whose variant changed.
+    pub fn edit_image(&mut self, name: &str, edit: impl FnOnce(&mut ImageJSONEntry)) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.images, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::Image, name, layer)
+    }
+
+    /// Edits the config of the atlas `name`. See `edit_image`.
+    pub fn edit_atlas(&mut self, name: &str, edit: impl FnOnce(&mut AtlasJSONEntry)) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.atlases, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::Atlas, name, layer)
+    }
+
+    /// Edits the config of the font `name`. See `edit_image`.
+    pub fn edit_ttf(&mut self, name: &str, edit: impl FnOnce(&mut TTFJSONEntry)) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.ttfs, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::TTF, name, layer)
+    }
+
+    /// Edits the config of the model `name`. See `edit_image`.
+    pub fn edit_geometry(&mut self, name: &str, edit: impl FnOnce(&mut GeometryJSONEntry)) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.geometry, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::Geometry, name, layer)
+    }
+
+    /// Edits the config of the particle system `name`. See `edit_image`.
+    pub fn edit_particle_system(
+        &mut self,
+        name: &str,
+        edit: impl FnOnce(&mut ParticleJSONEntry),
+    ) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.particles, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::Particle, name, layer)
+    }
+
+    /// Edits the config of the audio entry `name`. See `edit_image`.
+    pub fn edit_audio(&mut self, name: &str, edit: impl FnOnce(&mut AudioJSONEntry)) -> Result<Vec<AssetKey>, Error> {
+        let layer = edit_entry(&mut self.audio, &mut self.ids, name, edit)?;
+        self.entry_changed(AssetCategory::Audio, name, layer)
+    }
+
+    /// Renames an entry within its layer. Fails if `new_name` is taken. An entry of a
+    /// lower layer the old name overrode takes its place again. Returns the entries
+    /// whose variant changed.
+    pub fn rename(&mut self, category: AssetCategory, name: &str, new_name: &str) -> Result<Vec<AssetKey>, Error> {
+        let layer = match category {
+            AssetCategory::Image => edit_entry(&mut self.images, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::Atlas => edit_entry(&mut self.atlases, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::Geometry => edit_entry(&mut self.geometry, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::TTF => edit_entry(&mut self.ttfs, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::Particle => edit_entry(&mut self.particles, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::Audio => edit_entry(&mut self.audio, &mut self.ids, name, |c| c.set_name(new_name))?,
+            AssetCategory::Shader => return Err(not_authored(name)),
+            AssetCategory::Custom => return Err(custom_not_authored(name)),
+        };
+        self.entry_changed(category, name, layer)
+    }
+
+    /// Removes an entry from the database and from its layer's config on `save`. An
+    /// entry of a lower layer it overrode takes its place again. Returns the entries
+    /// whose variant changed.
+    pub fn remove(&mut self, category: AssetCategory, name: &str) -> Result<Vec<AssetKey>, Error> {
+        let layer = match category {
+            AssetCategory::Image => remove_entry(&mut self.images, name)?,
+            AssetCategory::Atlas => remove_entry(&mut self.atlases, name)?,
+            AssetCategory::Geometry => remove_entry(&mut self.geometry, name)?,
+            AssetCategory::TTF => remove_entry(&mut self.ttfs, name)?,
+            AssetCategory::Particle => remove_entry(&mut self.particles, name)?,
+            AssetCategory::Audio => remove_entry(&mut self.audio, name)?,
+            AssetCategory::Shader => return Err(not_authored(name)),
+            AssetCategory::Custom => return Err(custom_not_authored(name)),
+        };
+        self.entry_changed(category, name, layer)
+    }
+
+    /// Whether there are edits that `save` hasn't written yet.
+    pub fn has_unsaved_changes(&self) -> bool {
+        !self.dirty_layers.is_empty()
+    }
+
+    /// Writes the root config and the category configs of every edited layer back
+    /// through that layer's filesystem. Entries are sorted by name and unset fields
+    /// are left out, so the output diffs cleanly.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let mut dirty: Vec<usize> = self.dirty_layers.iter().copied().collect();
+        dirty.sort();
+
+        for layer in dirty {
+            let fs = self.layers[layer].clone();
+            let mut info = self.infos[layer].clone();
+
+            let images = layer_configs(&*fs, &self.images, layer, &info.image_cfg, |j: ImageJSON| j.images)?;
+            let atlases = layer_configs(&*fs, &self.atlases, layer, &info.atlas_cfg, |j: AtlasJSON| j.atlases)?;
+            let fonts = layer_configs(&*fs, &self.ttfs, layer, &info.ttf_cfg, |j: TTFJSON| j.fonts)?;
+            let models = layer_configs(&*fs, &self.geometry, layer, &info.geometry_cfg, |j: GeometryJSON| j.models)?;
+            let particles = layer_configs(&*fs, &self.particles, layer, &info.particle_cfg, |j: ParticleJSON| j.particles)?;
+            let audio = layer_configs(&*fs, &self.audio, layer, &info.audio_cfg, |j: AudioJSON| j.audio)?;
+
+            write_category(&*fs, &mut info.image_cfg, "images.json", !images.is_empty(), &ImageJSON { version: CONFIG_VERSION, images })?;
+            write_category(&*fs, &mut info.atlas_cfg, "atlases.json", !atlases.is_empty(), &AtlasJSON { version: CONFIG_VERSION, atlases })?;
+            write_category(&*fs, &mut info.ttf_cfg, "ttf.json", !fonts.is_empty(), &TTFJSON { version: CONFIG_VERSION, fonts })?;
+            write_category(&*fs, &mut info.geometry_cfg, "geometry.json", !models.is_empty(), &GeometryJSON { version: CONFIG_VERSION, models })?;
+            write_category(&*fs, &mut info.particle_cfg, "particle.json", !particles.is_empty(), &ParticleJSON { version: CONFIG_VERSION, particles })?;
+            write_category(&*fs, &mut info.audio_cfg, "audio.json", !audio.is_empty(), &AudioJSON { version: CONFIG_VERSION, audio })?;
+            update_config(&*fs, database_config(&*fs)?, &info)?;
+
+            self.infos[layer] = info;
+            self.dirty_layers.remove(&layer);
+        }
+
+        // Our own writes aren't changes for `poll_changes` to pick up.
+        self.watch_configs()
+    }
+}
@@ -0,0 +1,138 @@
+use super::id::EntryMap;
+use super::images::*;
+use std::collections::{HashMap, HashSet};
+
+/// Byte limits for loaded entries. Limits that are not set are unbounded.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBudget {
+    pub global: Option<usize>,
+    pub per_category: HashMap<AssetCategory, usize>,
+    /// Keep entries resident after their last handle drops, as a cache that is
+    /// evicted least recently fetched first once a limit needs the memory. Off by
+    /// default, so released entries are unloaded right away.
+    pub keep_released: bool,
+}
+
+impl MemoryBudget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn with_global(mut self, bytes: usize) -> Self {
+        self.global = Some(bytes);
+        self
+    }
+
+    pub fn with_category(mut self, category: AssetCategory, bytes: usize) -> Self {
+        self.per_category.insert(category, bytes);
+        self
+    }
+
+    pub fn keep_released(mut self) -> Self {
+        self.keep_released = true;
+        self
+    }
+}
+
+struct Residency {
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Tracks the size and last fetch of every resident entry, and which entries are
+/// pinned against eviction.
+#[derive(Default)]
+pub struct ResidencyTracker {
+    tick: u64,
+    resident: HashMap<AssetKey, Residency>,
+    pinned: HashSet<AssetKey>,
+}
+
+impl ResidencyTracker {
+    /// Records a fetch of `key`, which now occupies `bytes`.
+    pub fn touch(&mut self, key: &AssetKey, bytes: usize) {
+        self.tick += 1;
+        self.resident.insert(
+            key.clone(),
+            Residency {
+                bytes,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, key: &AssetKey) {
+        self.resident.remove(key);
+    }
+
+    pub fn pin(&mut self, key: &AssetKey) {
+        self.pinned.insert(key.clone());
+    }
+
+    pub fn unpin(&mut self, key: &AssetKey) {
+        self.pinned.remove(key);
+    }
+
+    pub fn is_pinned(&self, key: &AssetKey) -> bool {
+        self.pinned.contains(key)
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.resident.values().map(|r| r.bytes).sum()
+    }
+
+    pub fn category_bytes(&self, category: AssetCategory) -> usize {
+        self.resident
+            .iter()
+            .filter(|(k, _)| k.category == category)
+            .map(|(_, r)| r.bytes)
+            .sum()
+    }
+
+    /// Picks the least recently fetched entries to unload until `budget` is met.
+    /// Pinned entries and entries in `keep` are never selected.
+    pub fn select_evictions(&self, budget: &MemoryBudget, keep: &HashSet<AssetKey>) -> Vec<AssetKey> {
+        let mut total = self.total_bytes();
+        let mut per_category: HashMap<AssetCategory, usize> = HashMap::new();
+        for (key, r) in &self.resident {
+            *per_category.entry(key.category).or_default() += r.bytes;
+        }
+
+        let mut candidates: Vec<(&AssetKey, &Residency)> = self
+            .resident
+            .iter()
+            .filter(|(k, _)| !self.pinned.contains(*k) && !keep.contains(*k))
+            .collect();
+        candidates.sort_by_key(|(_, r)| r.last_used);
+
+        let mut evict = Vec::new();
+        for (key, r) in candidates {
+            let global_over = budget.global.map(|b| total > b).unwrap_or(false);
+            let category_over = budget
+                .per_category
+                .get(&key.category)
+                .map(|b| per_category[&key.category] > *b)
+                .unwrap_or(false);
+
+            if global_over || category_over {
+                total -= r.bytes;
+                *per_category.get_mut(&key.category).unwrap() -= r.bytes;
+                evict.push(key.clone());
+            }
+        }
+
+        evict
+    }
+}
+
+/// Adds every entry with outstanding handles to `keep`. Unloading those would not
+/// free their memory.
+pub fn collect_in_use<E: DatabaseEntry>(
+    entries: &EntryMap<E>,
+    category: AssetCategory,
+    keep: &mut HashSet<AssetKey>,
+) {
+    for entry in entries.values().filter(|e| e.handle_count() > 0) {
+        keep.insert(AssetKey::new(category, entry.name()));
+    }
+}
@@ -0,0 +1,259 @@
+use super::*;
+use serde::de::DeserializeOwned;
+use std::any::Any;
+
+impl CustomJSONEntry {
+    /// The category-specific fields as `T`.
+    pub fn data<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let fields = serde_json::Map::from_iter(self.data.clone());
+        serde_json::from_value(serde_json::Value::Object(fields)).map_err(|e| {
+            Error::LoadingError(LoadingError {
+                entry: self.name.clone(),
+                path: self.path.clone().unwrap_or_default(),
+                cause: e.to_string(),
+            })
+        })
+    }
+}
+
+/// Loads the entries of a custom category. Registered on a database with
+/// `Database::register_loader`.
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+
+    /// Loads `entry`. Its `path`, if any, is relative to the root of `fs`.
+    fn load(&self, entry: &CustomJSONEntry, fs: &dyn FileSystem) -> Result<Self::Asset, Error>;
+
+    /// Resident size of a loaded asset in bytes.
+    fn byte_size(&self, _asset: &Self::Asset) -> usize {
+        0
+    }
+}
+
+/// Loaded data of a custom entry, with its type erased.
+pub(crate) type ErasedAsset = Arc<dyn Any + Send + Sync>;
+
+/// `AssetLoader` with the asset type erased, so loaders of different types can
+/// share a map.
+pub(crate) trait ErasedLoader: Send + Sync {
+    fn load_any(&self, entry: &CustomJSONEntry, fs: &dyn FileSystem) -> Result<(ErasedAsset, usize), Error>;
+}
+
+impl<L: AssetLoader> ErasedLoader for L {
+    fn load_any(&self, entry: &CustomJSONEntry, fs: &dyn FileSystem) -> Result<(ErasedAsset, usize), Error> {
+        let asset = self.load(entry, fs)?;
+        let bytes = self.byte_size(&asset);
+        Ok((Arc::new(asset), bytes))
+    }
+}
+
+impl AssetKey {
+    /// Key of the entry `name` of the custom category `category`.
+    pub fn custom(category: &str, name: &str) -> Self {
+        AssetKey::new(AssetCategory::Custom, &format!("{}/{}", category, name))
+    }
+}
+
+pub struct CustomEntry {
+    pub cfg: CustomJSONEntry,
+    pub layer: usize,
+    /// `<category>/<name>`, the name of the entry's `AssetKey`.
+    key: String,
+    loaded: Option<(ErasedAsset, usize)>,
+}
+
+impl CustomEntry {
+    pub fn category(&self) -> &str {
+        &self.cfg.category
+    }
+}
+
+impl DatabaseEntry for CustomEntry {
+    type Config = CustomJSONEntry;
+    const CATEGORY: AssetCategory = AssetCategory::Custom;
+
+    fn from_cfg(cfg: CustomJSONEntry, layer: usize) -> Self {
+        Self {
+            key: AssetKey::custom(&cfg.category, &cfg.name).name,
+            cfg,
+            layer,
+            loaded: None,
+        }
+    }
+
+    fn cfg(&self) -> &CustomJSONEntry {
+        &self.cfg
+    }
+
+    fn cfg_mut(&mut self) -> &mut CustomJSONEntry {
+        &mut self.cfg
+    }
+
+    fn layer(&self) -> usize {
+        self.layer
+    }
+
+    fn name(&self) -> &str {
+        &self.key
+    }
+
+    /// Empty for entries that keep all their data inline.
+    fn path(&self) -> &str {
+        self.cfg.path.as_deref().unwrap_or_default()
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.loaded.is_some()
+    }
+
+    fn unload(&mut self) {
+        self.loaded = None;
+    }
+
+    /// Resident size of the loaded data as reported by the loader, 0 when unloaded.
+    fn byte_size(&self) -> usize {
+        self.loaded.as_ref().map(|(_, bytes)| *bytes).unwrap_or(0)
+    }
+
+    fn handle_count(&self) -> usize {
+        self.loaded
+            .as_ref()
+            .map(|(asset, _)| Arc::strong_count(asset) - 1)
+            .unwrap_or(0)
+    }
+
+    fn loaded_addr(&self) -> Option<usize> {
+        self.loaded.as_ref().map(|(asset, _)| data_addr(asset))
+    }
+}
+
+/// Parses the custom category configs of every layer, merging the entries of each
+/// category by name with the higher layer winning.
+pub(crate) fn load_custom(
+    layers: &[Arc<dyn FileSystem>],
+    infos: &[DatabaseJSON],
+    log: &mut Vec<MigrationRecord>,
+) -> Result<EntryMap<CustomEntry>, Error> {
+    let mut entries = EntryMap::default();
+    for (layer, (fs, info)) in layers.iter().zip(infos).enumerate() {
+        for (category, path) in &info.categories {
+            let cfg: CustomJSON = read_versioned(&**fs, path, log)?;
+            let mut names = HashSet::new();
+            for mut entry in cfg.entries {
+                if !names.insert(entry.name.clone()) {
+                    return Err(duplicate_name_error(&**fs, path, &entry.name));
+                }
+                entry.category = category.clone();
+                let key = AssetKey::custom(category, &entry.name);
+                if let Err(other) = entries.insert(CustomEntry::from_cfg(entry, layer)) {
+                    return Err(Error::from(collision_message(&key, &other)));
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn type_error<T>(key: &AssetKey) -> Error {
+    Error::from(format!("{} is not a {}", key.name, std::any::type_name::<T>()))
+}
+
+impl Database {
+    /// Registers the loader of the custom category `category`, replacing any
+    /// previous one. Entries it loaded before are unloaded.
+    pub fn register_loader<L: AssetLoader>(&mut self, category: &str, loader: L) {
+        self.loaders.insert(category.to_string(), Box::new(loader));
+
+        let mut unloaded = Vec::new();
+        for entry in self.custom.values_mut().filter(|e| e.category() == category && e.is_loaded()) {
+            entry.unload();
+            unloaded.push(AssetKey::new(AssetCategory::Custom, entry.name()));
+        }
+        for key in &unloaded {
+            self.forget_entry(key);
+        }
+    }
+
+    pub fn has_loader(&self, category: &str) -> bool {
+        self.loaders.contains_key(category)
+    }
+
+    /// Every custom category declared by any layer, sorted.
+    pub fn custom_categories(&self) -> Vec<&str> {
+        let mut categories: Vec<&str> = self
+            .infos
+            .iter()
+            .flat_map(|info| info.categories.keys())
+            .map(|c| c.as_str())
+            .collect();
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+
+    /// Names of the entries of the custom category `category`, sorted.
+    pub fn custom_names(&self, category: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .custom
+            .values()
+            .filter(|e| e.category() == category)
+            .map(|e| e.cfg.name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn custom_entry(&self, category: &str, name: &str) -> Option<&CustomEntry> {
+        self.custom.get(&AssetKey::custom(category, name).name)
+    }
+
+    /// Fetches the entry `name` of the custom category `category`, loading it with
+    /// the category's loader on first use. Fails if no loader is registered or if
+    /// the loader produces something other than `T`. Custom entries count against
+    /// the memory budget with the size their loader reports.
+    pub fn fetch_custom<T: Send + Sync + 'static>(&mut self, category: &str, name: &str) -> Result<Asset<T>, Error> {
+        self.fetch_custom_key(&AssetKey::custom(category, name))
+    }
+
+    pub(crate) fn fetch_custom_key<T: Send + Sync + 'static>(&mut self, key: &AssetKey) -> Result<Asset<T>, Error> {
+        self.fetch_custom_erased(&key.name)?
+            .downcast()
+            .map_err(|_| type_error::<T>(key))
+    }
+
+    /// Fetches the custom entry with the key name `name` without knowing its type,
+    /// e.g. for `fetch`.
+    pub(crate) fn fetch_custom_erased(&mut self, name: &str) -> Result<Asset<dyn Any + Send + Sync>, Error> {
+        self.release_dropped();
+
+        let entry = match self.custom.get_mut(name) {
+            Some(entry) => entry,
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+
+        if entry.loaded.is_none() {
+            let loader = self
+                .loaders
+                .get(entry.category())
+                .ok_or_else(|| Error::from(format!("no loader registered for category {}", entry.category())))?;
+            if entry.cfg.path.is_some() {
+                watch_source(&mut self.source_watchers, &self.layers, entry.layer, entry.path());
+            }
+            entry.loaded = Some(loader.load_any(&entry.cfg, &*self.layers[entry.layer])?);
+        }
+
+        let (data, bytes) = entry.loaded.clone().unwrap();
+        let key = AssetKey::new(AssetCategory::Custom, name);
+        self.note_fetch(&key, bytes);
+        Ok(Asset::new(key, data, self.releases.clone()))
+    }
+
+    /// Drops the loaded data of a custom entry. The next fetch loads it again.
+    pub fn unload_custom(&mut self, category: &str, name: &str) -> Result<(), Error> {
+        self.unload(AssetCategory::Custom, &AssetKey::custom(category, name).name)
+    }
+}
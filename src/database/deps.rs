@@ -0,0 +1,175 @@
+use super::*;
+use std::any::Any;
+
+/// A handle to loaded data of any category.
+#[derive(Clone)]
+pub enum AnyAsset {
+    Image(Asset<ImageLoadInfo<u8>>),
+    Atlas(Asset<Atlas>),
+    Model(Asset<Model>),
+    TTF(Asset<TTFont>),
+    Particle(Asset<ParticleSystem>),
+    Audio(Asset<AudioClip>),
+    Shader(Asset<Shader>),
+    /// Custom entries, whose type only their loader knows. See `Asset::downcast`.
+    Custom(Asset<dyn Any + Send + Sync>),
+}
+
+impl AnyAsset {
+    pub fn key(&self) -> &AssetKey {
+        match self {
+            AnyAsset::Image(a) => a.key(),
+            AnyAsset::Atlas(a) => a.key(),
+            AnyAsset::Model(a) => a.key(),
+            AnyAsset::TTF(a) => a.key(),
+            AnyAsset::Particle(a) => a.key(),
+            AnyAsset::Audio(a) => a.key(),
+            AnyAsset::Shader(a) => a.key(),
+            AnyAsset::Custom(a) => a.key(),
+        }
+    }
+}
+
+/// Dependencies of every resident entry, indexed both ways so budget checks and
+/// unloads don't have to walk every loaded entry.
+#[derive(Default)]
+pub struct DependencyIndex {
+    dependencies: HashMap<AssetKey, Vec<AssetKey>>,
+    dependents: HashMap<AssetKey, HashSet<AssetKey>>,
+}
+
+impl DependencyIndex {
+    /// Records the dependencies of `key`, which just became resident.
+    pub fn record(&mut self, key: &AssetKey, deps: Vec<AssetKey>) {
+        self.forget(key);
+        for dep in &deps {
+            self.dependents.entry(dep.clone()).or_default().insert(key.clone());
+        }
+        self.dependencies.insert(key.clone(), deps);
+    }
+
+    /// Drops `key`, which is no longer resident.
+    pub fn forget(&mut self, key: &AssetKey) {
+        for dep in self.dependencies.remove(key).unwrap_or_default() {
+            if let Some(dependents) = self.dependents.get_mut(&dep) {
+                dependents.remove(key);
+                if dependents.is_empty() {
+                    self.dependents.remove(&dep);
+                }
+            }
+        }
+    }
+
+    /// Resident entries that depend on `key`.
+    pub fn dependents(&self, key: &AssetKey) -> Vec<AssetKey> {
+        let mut dependents: Vec<AssetKey> = self.dependents.get(key).into_iter().flatten().cloned().collect();
+        dependents.sort();
+        dependents
+    }
+
+    /// Every entry some resident entry depends on.
+    pub fn needed(&self) -> impl Iterator<Item = &AssetKey> {
+        self.dependents.keys()
+    }
+}
+
+impl Database {
+    fn declared_dependencies(&self, key: &AssetKey) -> Option<Vec<AssetKey>> {
+        let deps = match key.category {
+            AssetCategory::Image => self.images.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::Atlas => self.atlases.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::Geometry => self.geometry.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::TTF => self.ttfs.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::Particle => self.particles.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::Audio => self.audio.get(&key.name)?.cfg.depends_on(),
+            AssetCategory::Shader => &[],
+            AssetCategory::Custom => self.custom.get(&key.name)?.cfg.depends_on(),
+        };
+        Some(deps.to_vec())
+    }
+
+    /// Image or atlas entry a material texture name refers to.
+    fn resolve_texture(&self, texture: &str) -> Option<AssetKey> {
+        if self.images.contains_key(texture) {
+            Some(AssetKey::new(AssetCategory::Image, texture))
+        } else if self.atlases.contains_key(texture) {
+            Some(AssetKey::new(AssetCategory::Atlas, texture))
+        } else {
+            None
+        }
+    }
+
+    /// Dependencies discovered from loaded data: the textures of a model's materials.
+    fn discovered_dependencies(&self, key: &AssetKey) -> Vec<AssetKey> {
+        let model = match key.category {
+            AssetCategory::Geometry => self.geometry.get(&key.name).and_then(|e| e.loaded.as_ref()),
+            _ => None,
+        };
+
+        let mut deps = Vec::new();
+        for mesh in model.iter().flat_map(|m| m.meshes.iter()) {
+            for texture in mesh.material.textures.values() {
+                if let Some(dep) = self.resolve_texture(texture) {
+                    if !deps.contains(&dep) {
+                        deps.push(dep);
+                    }
+                }
+            }
+        }
+        deps
+    }
+
+    /// Direct dependencies of an entry: those declared in its `depends_on`, plus
+    /// those discovered from its data once it is loaded.
+    pub fn dependencies(&self, category: AssetCategory, name: &str) -> Vec<AssetKey> {
+        let key = AssetKey::new(category, name);
+        let mut deps = self.declared_dependencies(&key).unwrap_or_default();
+        for dep in self.discovered_dependencies(&key) {
+            if !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        }
+        deps
+    }
+
+    /// Loaded entries that directly depend on the entry `name`.
+    pub fn loaded_dependents(&self, category: AssetCategory, name: &str) -> Vec<AssetKey> {
+        self.dependency_index.dependents(&AssetKey::new(category, name))
+    }
+
+    /// Fetches an entry of any category.
+    pub fn fetch(&mut self, category: AssetCategory, name: &str) -> Result<AnyAsset, Error> {
+        Ok(match category {
+            AssetCategory::Image => AnyAsset::Image(self.fetch_image(name)?),
+            AssetCategory::Atlas => AnyAsset::Atlas(self.fetch_atlas(name)?),
+            AssetCategory::Geometry => AnyAsset::Model(self.fetch_model(name)?),
+            AssetCategory::TTF => AnyAsset::TTF(self.fetch_ttf(name)?),
+            AssetCategory::Particle => AnyAsset::Particle(self.fetch_particle_system(name)?),
+            AssetCategory::Audio => AnyAsset::Audio(self.fetch_audio(name)?),
+            AssetCategory::Shader => AnyAsset::Shader(self.fetch_shader(name)?),
+            AssetCategory::Custom => AnyAsset::Custom(self.fetch_custom_erased(name)?),
+        })
+    }
+
+    /// Fetches an entry and, transitively, everything it depends on. The entry comes
+    /// first in the returned handles; holding them keeps the whole closure loaded.
+    pub fn fetch_with_dependencies(&mut self, category: AssetCategory, name: &str) -> Result<Vec<AnyAsset>, Error> {
+        let mut fetched = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![AssetKey::new(category, name)];
+
+        while let Some(key) = stack.pop() {
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+
+            fetched.push(self.fetch(key.category, &key.name)?);
+            // Dependencies discovered from the data are known only after loading.
+            let mut deps = self.dependencies(key.category, &key.name);
+            deps.reverse();
+            stack.extend(deps);
+        }
+
+        Ok(fetched)
+    }
+}
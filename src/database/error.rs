@@ -10,7 +10,10 @@ pub struct LookupError {
 #[derive(Debug)]
 pub struct LoadingError {
     pub entry: String,
+    /// The file the entry was loaded from. Empty when no file is involved.
     pub path: String,
+    /// Why loading failed.
+    pub cause: String,
 }
 
 impl fmt::Display for SlotError {
@@ -31,11 +34,14 @@ impl fmt::Display for LookupError {
 
 impl fmt::Display for LoadingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Failed to load requested entry {} in database! Attempted path: {}",
-            self.entry, self.path
-        )
+        write!(f, "Failed to load requested entry {} in database!", self.entry)?;
+        if !self.path.is_empty() {
+            write!(f, " Attempted path: {}", self.path)?;
+        }
+        if !self.cause.is_empty() {
+            write!(f, " Cause: {}", self.cause)?;
+        }
+        Ok(())
     }
 }
 #[derive(Debug)]
@@ -45,39 +51,67 @@ pub enum Error {
     SlotError(),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::LookupError(e) => e.fmt(f),
+            Error::LoadingError(e) => e.fmt(f),
+            Error::SlotError() => SlotError {}.fmt(f),
+        }
+    }
+}
+
+/// The message of an error raised below the entry level, which has no entry name.
+pub(crate) fn error_cause(err: Error) -> String {
+    match err {
+        Error::LoadingError(e) if e.entry == "[UNKNOWN]" => e.cause,
+        err => err.to_string(),
+    }
+}
+
 impl From<String> for Error {
     fn from(value: String) -> Self {
-        return Error::LoadingError(LoadingError {
+        Error::LoadingError(LoadingError {
             entry: "[UNKNOWN]".to_string(),
-            path: value,
-        });
+            path: String::new(),
+            cause: value,
+        })
+    }
+}
+
+impl From<&str> for Error {
+    fn from(value: &str) -> Self {
+        Error::from(value.to_string())
     }
 }
 
 impl From<image::ImageError> for Error {
     fn from(value: image::ImageError) -> Self {
-        return Error::LoadingError(LoadingError {
+        Error::LoadingError(LoadingError {
             entry: "[UNKNOWN]".to_string(),
-            path: value.to_string(),
-        });
+            path: String::new(),
+            cause: value.to_string(),
+        })
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        return Error::LoadingError(LoadingError {
+        Error::LoadingError(LoadingError {
             entry: "[UNKNOWN]".to_string(),
-            path: value.to_string(),
-        });
+            path: String::new(),
+            cause: value.to_string(),
+        })
     }
 }
 
 impl From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
-        return Error::LoadingError(LoadingError {
+        Error::LoadingError(LoadingError {
             entry: "JSON FILE".to_string(),
-            path: value.to_string(),
-        });
+            path: String::new(),
+            cause: value.to_string(),
+        })
     }
 }
 //impl From<ash::vk::Result> for GPUError {
@@ -1,3 +1,5 @@
+use super::error::*;
+use super::vfs::*;
 use dashi::Rect2D;
 use std::collections::HashMap;
 
@@ -17,20 +19,43 @@ pub struct TTFont {
 }
 
 impl TTFont {
-    /// Creates a new TTFont instance by loading a font from a specified file path.
-    pub fn new(file_path: &str, width: u32, height: u32, font_size: f32, range: &[char]) -> Self {
+    /// Resident size of the glyph atlas and glyph table in bytes.
+    pub fn byte_size(&self) -> usize {
+        let atlas = self.atlas.as_ref().map(|a| a.len()).unwrap_or(0);
+        atlas + self.glyphs.len() * std::mem::size_of::<(char, Glyph)>()
+    }
+
+    /// Creates a new TTFont instance by loading a font from a file in `fs`.
+    pub fn new(
+        fs: &dyn FileSystem,
+        file_path: &str,
+        width: u32,
+        height: u32,
+        font_size: f32,
+        range: &[char],
+    ) -> Result<Self, Error> {
+        let font_data = fs.read(file_path)?;
+        TTFont::from_bytes(font_data, width, height, font_size, range)
+    }
+
+    /// Creates a new TTFont instance from font file data held in memory.
+    pub fn from_bytes(
+        font_data: Vec<u8>,
+        width: u32,
+        height: u32,
+        font_size: f32,
+        range: &[char],
+    ) -> Result<Self, Error> {
         // Create a new bitmap (initialized to zero)
         let mut bitmap = vec![0u8; (width * height) as usize];
 
-        let font_data = std::fs::read(file_path).unwrap();
         let font = fontdue::Font::from_bytes(
             font_data,
             fontdue::FontSettings {
                 scale: 80.0,
                 ..Default::default()
             },
-        )
-        .unwrap();
+        )?;
 
         // Coordinates to keep track of where to draw the next glyph
         let mut cursor_x: u32 = font_size as u32;
@@ -61,8 +86,8 @@ impl TTFont {
                     *ch,
                     Glyph {
                         bounds: Rect2D {
-                            x: cursor_x as u32,
-                            y: cursor_y as u32,
+                            x: cursor_x,
+                            y: cursor_y,
                             w: metrics.width as u32,
                             h: metrics.height as u32,
                         },
@@ -83,11 +108,11 @@ impl TTFont {
             }
         }
 
-        Self {
+        Ok(Self {
             glyphs: glyph_map,
             atlas: Some(bitmap),
             atlas_width: width,
             atlas_height: height,
-        }
+        })
     }
 }
@@ -0,0 +1,207 @@
+use super::vfs::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Names the root config of a database may have, in the order they're looked for.
+pub const DATABASE_CONFIGS: &[&str] = &[
+    "database.json",
+    "database.ron",
+    "database.toml",
+    "database.yaml",
+    "database.yml",
+];
+
+/// The root config of the database in `fs`. Falls back to `database.json` so a
+/// missing database reports the usual name. Fails if there are several, since
+/// edits to the one not read would be silently ignored.
+pub fn database_config(fs: &dyn FileSystem) -> Result<&'static str, String> {
+    let found: Vec<&'static str> = DATABASE_CONFIGS.iter().copied().filter(|path| fs.exists(path)).collect();
+    match found[..] {
+        [] => Ok(DATABASE_CONFIGS[0]),
+        [path] => Ok(path),
+        _ => Err(format!("{} has several root configs ({}); keep one", fs.root(), found.join(", "))),
+    }
+}
+
+/// Text format of a config file, picked by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Ron,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Unknown extensions are read as JSON.
+    pub fn from_path(path: &str) -> Self {
+        let extension = path.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ron") => ConfigFormat::Ron,
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Ron => "RON",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Yaml => "YAML",
+        }
+    }
+
+    fn error(self, message: impl std::fmt::Display) -> String {
+        format!("invalid {}: {}", self.name(), message)
+    }
+
+    /// RON keeps struct and enum syntax that has no JSON equivalent (a unit
+    /// variant reads back as `()`), so its value form drops enum variant names.
+    /// It is only used to migrate older layouts, which have no enums; configs are
+    /// never written back from it.
+    pub fn has_value_form(self) -> bool {
+        self != ConfigFormat::Ron
+    }
+
+    /// Parses `text` into a JSON value for the migration chain. Lossy for RON, see
+    /// `has_value_form`.
+    pub fn to_value(self, text: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| self.error(e)),
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| self.error(e)),
+            ConfigFormat::Yaml => {
+                let mut yaml: serde_yaml::Value = serde_yaml::from_str(text).map_err(|e| self.error(e))?;
+                untag_yaml(&mut yaml);
+                serde_json::to_value(yaml).map_err(|e| self.error(e))
+            }
+            ConfigFormat::Ron => ron_options()
+                .from_str::<ron::Value>(text)
+                .map_err(|e| self.error(e))?
+                .into_rust()
+                .map_err(|e| self.error(e)),
+        }
+    }
+
+    /// Deserializes `text` straight into `T`, so errors carry a line and column.
+    pub fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T, String> {
+        self.parse_checked(text, &mut Vec::new())
+    }
+
+    /// Like `parse`, also adding the path of every key `T` has no field for to
+    /// `unknown`.
+    pub fn parse_checked<T: DeserializeOwned>(self, text: &str, unknown: &mut Vec<String>) -> Result<T, String> {
+        match self {
+            ConfigFormat::Json => {
+                let mut de = serde_json::Deserializer::from_str(text);
+                let value = serde_ignored::deserialize(&mut de, |path| unknown.push(key_path(&path)))
+                    .map_err(|e| self.error(e))?;
+                de.end().map_err(|e| self.error(e))?;
+                Ok(value)
+            }
+            ConfigFormat::Ron => {
+                let mut de = ron::Deserializer::from_str_with_options(text, ron_options()).map_err(|e| self.error(e))?;
+                let value = serde_ignored::deserialize(&mut de, |path| unknown.push(key_path(&path)))
+                    .map_err(|e| self.error(de.span_error(e)))?;
+                de.end().map_err(|e| self.error(de.span_error(e)))?;
+                Ok(value)
+            }
+            ConfigFormat::Toml => {
+                let de = toml::Deserializer::new(text);
+                serde_ignored::deserialize(de, |path| unknown.push(key_path(&path))).map_err(|e| self.error(e))
+            }
+            ConfigFormat::Yaml => {
+                let mut ignored = Vec::new();
+                let de = serde_yaml::Deserializer::from_str(text);
+                match serde_ignored::deserialize(de, |path| ignored.push(key_path(&path))) {
+                    Ok(value) => {
+                        unknown.extend(ignored);
+                        Ok(value)
+                    }
+                    // Enums may also be written as `{ variant: value }` maps, which
+                    // only the value form reads. Errors still point at the text.
+                    Err(err) => match self.to_value(text).map(|value| from_value_checked(value, unknown)) {
+                        Ok(Ok(value)) => Ok(value),
+                        _ => Err(self.error(err)),
+                    },
+                }
+            }
+        }
+    }
+
+    /// The `version` field of a config, 0 if it has none.
+    pub fn version(self, text: &str) -> Result<u32, String> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            #[serde(default)]
+            version: u64,
+        }
+        let version = self.parse::<Versioned>(text)?.version;
+        u32::try_from(version).map_err(|_| format!("invalid config version {}", version))
+    }
+
+    pub fn to_string<T: Serialize>(self, value: &T) -> Result<String, String> {
+        let mut text = match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| self.error(e))?,
+            ConfigFormat::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+                .map_err(|e| self.error(e))?,
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| self.error(e))?,
+            // Through a JSON value so enums are written as maps, which `to_value`
+            // reads back without tags.
+            ConfigFormat::Yaml => serde_json::to_value(value)
+                .map_err(|e| e.to_string())
+                .and_then(|v| serde_yaml::to_string(&v).map_err(|e| e.to_string()))
+                .map_err(|e| self.error(e))?,
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+/// Deserializes `value` into `T`, adding the path of every key `T` has no field
+/// for to `unknown`.
+pub fn from_value_checked<T: DeserializeOwned>(value: Value, unknown: &mut Vec<String>) -> Result<T, serde_json::Error> {
+    serde_ignored::deserialize(value, |path| unknown.push(key_path(&path)))
+}
+
+/// Renders the path of an ignored key like `atlases[0].entries[2].nmae`.
+fn key_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", key_path(parent), index),
+        Path::Map { parent, key } => match key_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => key_path(parent),
+    }
+}
+
+/// Lets RON configs leave out `Some(..)` around optional fields, like the other
+/// formats do.
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+}
+
+/// Turns YAML tags (`!graphics x`) into the externally tagged maps serde uses
+/// for enums in JSON (`{ graphics: x }`).
+fn untag_yaml(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Tagged(tagged) => {
+            let tag = tagged.tag.to_string().trim_start_matches('!').to_string();
+            let mut inner = std::mem::replace(&mut tagged.value, serde_yaml::Value::Null);
+            untag_yaml(&mut inner);
+            let mut map = serde_yaml::Mapping::new();
+            map.insert(serde_yaml::Value::String(tag), inner);
+            *value = serde_yaml::Value::Mapping(map);
+        }
+        serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(untag_yaml),
+        serde_yaml::Value::Mapping(map) => map.iter_mut().for_each(|(_, v)| untag_yaml(v)),
+        _ => {}
+    }
+}
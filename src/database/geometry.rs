@@ -1,10 +1,12 @@
 use glam::*;
 pub type Index = u32;
 use glam::{Vec2, Vec3};
-use gltf::Gltf;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
+use super::error::*;
+use super::vfs::*;
+use base64::Engine;
 
 #[derive(Debug, Clone)]
 pub struct Vertex {
@@ -15,7 +17,7 @@ pub struct Vertex {
     pub bone_weights: [f32; 4],
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub enum TextureType {
     Diffuse,
     Specular,
@@ -26,7 +28,7 @@ pub enum TextureType {
     Albedo,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Material {
     pub name: String,
     pub textures: HashMap<TextureType, String>,
@@ -34,6 +36,8 @@ pub struct Material {
 
 #[derive(Debug, Clone)]
 pub struct Mesh {
+    pub name: String,
+    pub render_mask: Option<String>,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<Index>,
     pub material: Material,
@@ -44,11 +48,62 @@ pub struct Model {
     pub meshes: Vec<Mesh>,
 }
 
-pub fn load_gltf_model<P: AsRef<Path>>(path: P) -> Option<Model> {
-    let (gltf, buffers, _) = gltf::import(path).expect("Failed to load glTF file");
+impl Model {
+    /// Resident size of the vertex and index data in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.meshes
+            .iter()
+            .map(|m| {
+                m.vertices.len() * std::mem::size_of::<Vertex>()
+                    + m.indices.len() * std::mem::size_of::<Index>()
+            })
+            .sum()
+    }
+}
+
+/// Loads a glTF model from `fs`. External buffers are resolved next to `path`.
+pub fn load_gltf_model(fs: &dyn FileSystem, path: &str) -> Result<Model, Error> {
+    let bytes = fs.read(path)?;
+    let read_uri = |uri: &str| fs.read(&sibling_path(path, uri)).ok();
+    load_gltf_model_from_slice(&bytes, &read_uri)
+}
+
+fn gltf_error(message: impl std::fmt::Display) -> Error {
+    Error::from(format!("invalid glTF: {}", message))
+}
+
+/// Loads a glTF model from memory. `read_uri` resolves buffer URIs that point at
+/// external files; embedded `data:` URIs and GLB blobs are handled here.
+pub fn load_gltf_model_from_slice(
+    bytes: &[u8],
+    read_uri: &dyn Fn(&str) -> Option<Vec<u8>>,
+) -> Result<Model, Error> {
+    let gltf = gltf::Gltf::from_slice(bytes).map_err(gltf_error)?;
+    let mut buffers = Vec::new();
+    for buffer in gltf.document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| gltf_error("missing GLB binary chunk"))?,
+            gltf::buffer::Source::Uri(uri) => match uri.strip_prefix("data:") {
+                Some(data) => data
+                    .split(',')
+                    .nth(1)
+                    .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+                    .ok_or_else(|| gltf_error("malformed data URI"))?,
+                None => read_uri(uri).ok_or_else(|| gltf_error(format!("cannot read buffer {}", uri)))?,
+            },
+        };
+        buffers.push(data);
+    }
+
+    model_from_document(&gltf.document, &buffers)
+}
+
+fn model_from_document(gltf: &gltf::Document, buffers: &[Vec<u8>]) -> Result<Model, Error> {
     let mut meshes = Vec::new();
 
     for mesh in gltf.meshes() {
+        let mesh_name = mesh.name().unwrap_or("Unknown");
+        let missing = |what: &str| gltf_error(format!("mesh {} has no {}", mesh_name, what));
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
         let mut material = None;
@@ -59,13 +114,17 @@ pub fn load_gltf_model<P: AsRef<Path>>(path: P) -> Option<Model> {
             let mut bone_weights: Vec<[f32; 4]> = Vec::new();
 
             // Extract Positions
-            let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+            let positions: Vec<[f32; 3]> = reader.read_positions().ok_or_else(|| missing("positions"))?.collect();
 
             // Extract Normals
-            let normals: Vec<[f32; 3]> = reader.read_normals()?.collect();
+            let normals: Vec<[f32; 3]> = reader.read_normals().ok_or_else(|| missing("normals"))?.collect();
 
             // Extract Texture Coordinates
-            let tex_coords: Vec<[f32; 2]> = reader.read_tex_coords(0)?.into_f32().collect();
+            let tex_coords: Vec<[f32; 2]> = reader
+                .read_tex_coords(0)
+                .ok_or_else(|| missing("texture coordinates"))?
+                .into_f32()
+                .collect();
 
             // Extract Bone Weights
             if let Some(joints) = reader.read_joints(0) {
@@ -80,8 +139,17 @@ pub fn load_gltf_model<P: AsRef<Path>>(path: P) -> Option<Model> {
                 bone_weights.resize(positions.len(), [0.0, 0.0, 0.0, 0.0]);
             }
 
+            let count = positions.len();
+            let lengths = [normals.len(), tex_coords.len(), bone_ids.len(), bone_weights.len()];
+            if lengths.iter().any(|len| *len != count) {
+                return Err(gltf_error(format!("mesh {} has attributes of different lengths", mesh_name)));
+            }
+
+            // Indices of later primitives follow the vertices already added.
+            let base = vertices.len() as u32;
+
             // Collect vertex data
-            for i in 0..positions.len() {
+            for i in 0..count {
                 vertices.push(Vertex {
                     pos: Vec3::from(positions[i]),
                     normal: Vec3::from(normals[i]),
@@ -98,9 +166,14 @@ pub fn load_gltf_model<P: AsRef<Path>>(path: P) -> Option<Model> {
 
             // Extract Indices
             if let Some(indices_data) = reader.read_indices() {
-                indices.extend(indices_data.into_u32());
+                for index in indices_data.into_u32() {
+                    if index as usize >= count {
+                        return Err(gltf_error(format!("mesh {} has an index past its vertices", mesh_name)));
+                    }
+                    indices.push(base + index);
+                }
             }
-            
+
             let mut mat_name = "Unknown".to_string();
             // Extract Material Information
             let mat = primitive.material();
@@ -139,11 +212,13 @@ pub fn load_gltf_model<P: AsRef<Path>>(path: P) -> Option<Model> {
         }
 
         meshes.push(Mesh {
+            name: mesh_name.to_string(),
+            render_mask: None,
             vertices,
             indices,
-            material: material.unwrap(),
+            material: material.ok_or_else(|| missing("primitives"))?,
         });
     }
 
-    Some(Model { meshes })
+    Ok(Model { meshes })
 }
@@ -0,0 +1,156 @@
+use super::images::*;
+use std::any::Any;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Entries whose last handle was dropped, along with the address of the data the
+/// handle pointed to. The database drains this to make unused entries evictable.
+#[derive(Clone, Default)]
+pub struct ReleaseQueue {
+    released: Arc<Mutex<Vec<(AssetKey, usize)>>>,
+}
+
+impl ReleaseQueue {
+    fn push(&self, key: AssetKey, addr: usize) {
+        self.released.lock().unwrap().push((key, addr));
+    }
+
+    pub fn drain(&self) -> Vec<(AssetKey, usize)> {
+        std::mem::take(&mut *self.released.lock().unwrap())
+    }
+}
+
+/// Address of the data behind `data`, used to tell reloaded data apart from the
+/// data a handle was created for.
+pub fn data_addr<T: ?Sized>(data: &Arc<T>) -> usize {
+    Arc::as_ptr(data) as *const () as usize
+}
+
+/// Whether the data a dropped handle pointed to is still loaded in `entry` and
+/// no other handle to it remains.
+pub fn is_released<E: DatabaseEntry>(entry: &E, addr: usize) -> bool {
+    entry.loaded_addr() == Some(addr) && entry.handle_count() == 0
+}
+
+/// Queues a release when dropped. Sits after the data in `Shared`, so the data's
+/// reference is already gone when the database looks at the release.
+struct ReleaseGuard {
+    key: AssetKey,
+    addr: usize,
+    releases: ReleaseQueue,
+}
+
+impl Drop for ReleaseGuard {
+    fn drop(&mut self) {
+        self.releases.push(self.key.clone(), self.addr);
+    }
+}
+
+/// State shared by a handle and its clones. Its guard runs exactly once, on the
+/// last drop among them, whichever thread that happens on.
+struct Shared<T: ?Sized> {
+    data: Arc<T>,
+    release: ReleaseGuard,
+}
+
+/// Cheap, cloneable, reference-counted handle to loaded asset data. Once every
+/// handle to an entry has been dropped, the entry becomes evictable: it stays
+/// resident until the memory budget needs its memory.
+pub struct Asset<T: ?Sized> {
+    key: AssetKey,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: ?Sized> Asset<T> {
+    pub(crate) fn new(key: AssetKey, data: Arc<T>, releases: ReleaseQueue) -> Self {
+        let release = ReleaseGuard {
+            key: key.clone(),
+            addr: data_addr(&data),
+            releases,
+        };
+        Self {
+            key,
+            shared: Arc::new(Shared {
+                data,
+                release,
+            }),
+        }
+    }
+
+    pub fn key(&self) -> &AssetKey {
+        &self.key
+    }
+
+    pub fn downgrade(&self) -> WeakAsset<T> {
+        WeakAsset {
+            key: self.key.clone(),
+            data: Arc::downgrade(&self.shared.data),
+            releases: self.shared.release.releases.clone(),
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &Asset<T>) -> bool {
+        Arc::ptr_eq(&self.shared.data, &other.shared.data)
+    }
+}
+
+impl<T: ?Sized> Clone for Asset<T> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for Asset<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.shared.data
+    }
+}
+
+impl Asset<dyn Any + Send + Sync> {
+    /// This handle as a handle to `T`, or itself if the data is something else.
+    pub fn downcast<T: Send + Sync + 'static>(self) -> Result<Asset<T>, Self> {
+        match self.shared.data.clone().downcast::<T>() {
+            Ok(data) => Ok(Asset::new(self.key.clone(), data, self.shared.release.releases.clone())),
+            Err(_) => Err(self),
+        }
+    }
+}
+
+/// Non-owning handle that doesn't keep its entry loaded, for caches.
+pub struct WeakAsset<T: ?Sized> {
+    key: AssetKey,
+    data: Weak<T>,
+    releases: ReleaseQueue,
+}
+
+impl<T: ?Sized> WeakAsset<T> {
+    pub fn key(&self) -> &AssetKey {
+        &self.key
+    }
+
+    /// Returns a strong handle if the entry is still loaded.
+    pub fn upgrade(&self) -> Option<Asset<T>> {
+        self.data
+            .upgrade()
+            .map(|data| Asset::new(self.key.clone(), data, self.releases.clone()))
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.data.strong_count() > 0
+    }
+}
+
+impl<T: ?Sized> Clone for WeakAsset<T> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            data: self.data.clone(),
+            releases: self.releases.clone(),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Stable 64-bit identifier of an entry, derived from its category and name. The
+/// same entry gets the same id on every run and platform, so ids can go into save
+/// files and network messages instead of names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AssetId(pub u64);
+
+fn category_label(category: AssetCategory) -> &'static str {
+    match category {
+        AssetCategory::Image => "image",
+        AssetCategory::Atlas => "atlas",
+        AssetCategory::Geometry => "geometry",
+        AssetCategory::TTF => "ttf",
+        AssetCategory::Particle => "particle",
+        AssetCategory::Audio => "audio",
+        AssetCategory::Shader => "shader",
+        AssetCategory::Custom => "custom",
+    }
+}
+
+impl AssetId {
+    /// FNV-1a over `<category>:<name>`.
+    pub fn of(category: AssetCategory, name: &str) -> Self {
+        let mut hash = FNV_OFFSET;
+        let label = category_label(category).bytes();
+        for byte in label.chain(std::iter::once(b':')).chain(name.bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        AssetId(hash)
+    }
+}
+
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl AssetKey {
+    pub fn id(&self) -> AssetId {
+        AssetId::of(self.category, &self.name)
+    }
+}
+
+/// The entries of one category, stored by id. Lookups by name hash the name, so
+/// no two entries of a map can share an id.
+pub struct EntryMap<E> {
+    entries: HashMap<AssetId, E>,
+}
+
+impl<E> Default for EntryMap<E> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<E: DatabaseEntry> EntryMap<E> {
+    fn id(name: &str) -> AssetId {
+        AssetId::of(E::CATEGORY, name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&E> {
+        self.entries.get(&Self::id(name)).filter(|e| e.name() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut E> {
+        self.entries.get_mut(&Self::id(name)).filter(|e| e.name() == name)
+    }
+
+    pub fn get_by_id(&self, id: AssetId) -> Option<&E> {
+        self.entries.get(&id)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds `entry`, returning the entry of the same name it replaces. Fails
+    /// without changing anything if a differently named entry has the same id,
+    /// returning that entry's key.
+    pub fn insert(&mut self, entry: E) -> Result<Option<E>, AssetKey> {
+        let id = Self::id(entry.name());
+        match self.entries.get(&id) {
+            Some(other) if other.name() != entry.name() => Err(AssetKey::new(E::CATEGORY, other.name())),
+            _ => Ok(self.entries.insert(id, entry)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<E> {
+        self.get(name)?;
+        self.entries.remove(&Self::id(name))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.values().map(|e| e.name())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &E> {
+        self.entries.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut E> {
+        self.entries.values_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&E) -> bool) {
+        self.entries.retain(|_, e| keep(e));
+    }
+}
+
+impl<E: DatabaseEntry> std::ops::Index<&str> for EntryMap<E> {
+    type Output = E;
+
+    fn index(&self, name: &str) -> &E {
+        self.get(name).expect("no entry with that name")
+    }
+}
+
+/// Maps the id of every entry back to its key.
+#[derive(Default)]
+pub struct IdTable {
+    keys: HashMap<AssetId, AssetKey>,
+}
+
+impl IdTable {
+    /// Records the id of `key`. Fails if a different entry already has that id.
+    pub fn insert(&mut self, key: AssetKey) -> Result<AssetId, AssetKey> {
+        let id = key.id();
+        match self.keys.get(&id) {
+            Some(other) if *other != key => Err(other.clone()),
+            _ => {
+                self.keys.insert(id, key);
+                Ok(id)
+            }
+        }
+    }
+
+    pub fn get(&self, id: AssetId) -> Option<&AssetKey> {
+        self.keys.get(&id)
+    }
+
+    pub fn remove(&mut self, key: &AssetKey) {
+        if self.keys.get(&key.id()) == Some(key) {
+            self.keys.remove(&key.id());
+        }
+    }
+
+    /// Whether `key` can be added without colliding with another entry.
+    pub fn is_free(&self, key: &AssetKey) -> bool {
+        match self.keys.get(&key.id()) {
+            Some(other) => other == key,
+            None => true,
+        }
+    }
+
+    /// Fails with a collision error if `key` can't be added.
+    pub fn check_free(&self, key: &AssetKey) -> Result<(), Error> {
+        match self.keys.get(&key.id()) {
+            Some(other) if other != key => Err(Error::from(collision_message(key, other))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records `key`, replacing whatever had its id. Callers check `check_free`
+    /// before changing anything.
+    pub fn record(&mut self, key: AssetKey) {
+        self.keys.insert(key.id(), key);
+    }
+}
+
+/// The error for a config that declares the entry `name` more than once.
+pub(crate) fn duplicate_name_error(fs: &dyn FileSystem, config: &str, name: &str) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: name.to_string(),
+        path: format!("{}/{}", fs.root(), config),
+        cause: "name is declared more than once".to_string(),
+    })
+}
+
+pub(crate) fn collision_message(key: &AssetKey, other: &AssetKey) -> String {
+    format!(
+        "asset id collision: {:?} {} and {:?} {} both map to {}",
+        key.category,
+        key.name,
+        other.category,
+        other.name,
+        key.id()
+    )
+}
+
+/// A collection of entries the id table is built from.
+pub trait EntryNames {
+    fn entry_names(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+}
+
+impl<E: DatabaseEntry> EntryNames for EntryMap<E> {
+    fn entry_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.names())
+    }
+}
+
+impl EntryNames for HashMap<String, ShaderEntry> {
+    fn entry_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.keys().map(|k| k.as_str()))
+    }
+}
+
+/// Adds every entry of `entries` to `table`, failing on the first id collision.
+pub fn collect_ids(entries: &dyn EntryNames, category: AssetCategory, table: &mut IdTable) -> Result<(), String> {
+    for name in entries.entry_names() {
+        let key = AssetKey::new(category, name);
+        if let Err(other) = table.insert(key.clone()) {
+            return Err(collision_message(&key, &other));
+        }
+    }
+    Ok(())
+}
+
+/// Name of the entry with id `id`, looked up in the category's own map.
+fn name_for_id<E: DatabaseEntry>(entries: &EntryMap<E>, id: AssetId) -> Result<String, Error> {
+    match entries.get_by_id(id) {
+        Some(entry) => Ok(entry.name().to_string()),
+        None => Err(Error::LookupError(LookupError {
+            entry: id.to_string(),
+        })),
+    }
+}
+
+impl Database {
+    /// Rebuilds the id table from the current entries.
+    pub(crate) fn rebuild_ids(&mut self) -> Result<(), Error> {
+        self.ids = Database::build_ids(&[
+            (AssetCategory::Image, &self.images),
+            (AssetCategory::Atlas, &self.atlases),
+            (AssetCategory::Geometry, &self.geometry),
+            (AssetCategory::TTF, &self.ttfs),
+            (AssetCategory::Particle, &self.particles),
+            (AssetCategory::Audio, &self.audio),
+            (AssetCategory::Shader, &self.shaders),
+            (AssetCategory::Custom, &self.custom),
+        ])?;
+        Ok(())
+    }
+
+    /// The id table of the entries of every category, failing on the first id
+    /// collision between categories. Lets a reload check its entries before
+    /// replacing anything.
+    pub(crate) fn build_ids(categories: &[(AssetCategory, &dyn EntryNames)]) -> Result<IdTable, String> {
+        let mut ids = IdTable::default();
+        for (category, entries) in categories {
+            collect_ids(*entries, *category, &mut ids)?;
+        }
+        Ok(ids)
+    }
+
+    /// Id of the entry `name`, if the database has it.
+    pub fn id_of(&self, category: AssetCategory, name: &str) -> Option<AssetId> {
+        let key = AssetKey::new(category, name);
+        match self.ids.get(key.id()) {
+            Some(found) if *found == key => Some(key.id()),
+            _ => None,
+        }
+    }
+
+    /// The entry an id belongs to, mostly for debugging.
+    pub fn lookup_id(&self, id: AssetId) -> Option<&AssetKey> {
+        self.ids.get(id)
+    }
+
+    pub fn fetch_image_by_id(&mut self, id: AssetId) -> Result<Asset<ImageLoadInfo<u8>>, Error> {
+        let name = name_for_id(&self.images, id)?;
+        self.fetch_image(&name)
+    }
+
+    pub fn fetch_atlas_by_id(&mut self, id: AssetId) -> Result<Asset<Atlas>, Error> {
+        let name = name_for_id(&self.atlases, id)?;
+        self.fetch_atlas(&name)
+    }
+
+    pub fn fetch_model_by_id(&mut self, id: AssetId) -> Result<Asset<Model>, Error> {
+        let name = name_for_id(&self.geometry, id)?;
+        self.fetch_model(&name)
+    }
+
+    pub fn fetch_ttf_by_id(&mut self, id: AssetId) -> Result<Asset<TTFont>, Error> {
+        let name = name_for_id(&self.ttfs, id)?;
+        self.fetch_ttf(&name)
+    }
+
+    pub fn fetch_particle_system_by_id(&mut self, id: AssetId) -> Result<Asset<ParticleSystem>, Error> {
+        let name = name_for_id(&self.particles, id)?;
+        self.fetch_particle_system(&name)
+    }
+
+    pub fn fetch_audio_by_id(&mut self, id: AssetId) -> Result<Asset<AudioClip>, Error> {
+        let name = name_for_id(&self.audio, id)?;
+        self.fetch_audio(&name)
+    }
+
+    pub fn fetch_shader_by_id(&mut self, id: AssetId) -> Result<Asset<Shader>, Error> {
+        let name = match self.ids.get(id) {
+            Some(key) if key.category == AssetCategory::Shader => key.name.clone(),
+            _ => {
+                return Err(Error::LookupError(LookupError {
+                    entry: id.to_string(),
+                }))
+            }
+        };
+        self.fetch_shader(&name)
+    }
+
+    pub fn fetch_custom_by_id<T: Send + Sync + 'static>(&mut self, id: AssetId) -> Result<Asset<T>, Error> {
+        let name = name_for_id(&self.custom, id)?;
+        self.fetch_custom_key(&AssetKey::new(AssetCategory::Custom, &name))
+    }
+}
@@ -1,127 +1,454 @@
+use super::error::*;
 use super::json::*;
 use super::load_funcs::*;
+use super::geometry::*;
 use super::TTFont;
-use std::collections::HashMap;
+use super::handle::*;
+use super::stats::*;
+use super::vfs::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum AssetCategory {
+    Image,
+    Atlas,
+    Geometry,
+    TTF,
+    Particle,
+    Audio,
+    Shader,
+    /// Entries of the categories in `DatabaseJSON::categories`. Their key names are
+    /// `<category>/<name>`, see `AssetKey::custom`.
+    Custom,
+}
+
+impl AssetCategory {
+    pub const ALL: [AssetCategory; 8] = [
+        AssetCategory::Image,
+        AssetCategory::Atlas,
+        AssetCategory::Geometry,
+        AssetCategory::TTF,
+        AssetCategory::Particle,
+        AssetCategory::Audio,
+        AssetCategory::Shader,
+        AssetCategory::Custom,
+    ];
+}
+
+/// Identifies an entry across every category of the database.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct AssetKey {
+    pub category: AssetCategory,
+    pub name: String,
+}
+
+impl AssetKey {
+    pub fn new(category: AssetCategory, name: &str) -> Self {
+        Self {
+            category,
+            name: name.to_string(),
+        }
+    }
+}
+
+/// Common view over the entry types stored in the database.
+pub trait DatabaseEntry {
+    type Config: Serialize + EntryConfig + Clone;
+    const CATEGORY: AssetCategory;
+
+    /// A new, unloaded entry supplied by `layer`.
+    fn from_cfg(cfg: Self::Config, layer: usize) -> Self
+    where
+        Self: Sized;
+    fn cfg(&self) -> &Self::Config;
+    fn cfg_mut(&mut self) -> &mut Self::Config;
+    /// Index of the layer that supplied the entry. Its path resolves against that
+    /// layer.
+    fn layer(&self) -> usize;
+    fn name(&self) -> &str;
+    /// Source file of the entry, relative to the root of its layer.
+    fn path(&self) -> &str;
+    fn is_loaded(&self) -> bool;
+    fn unload(&mut self);
+    /// Resident size of the loaded data in bytes, 0 when unloaded.
+    fn byte_size(&self) -> usize;
+    /// Number of outstanding asset handles to the loaded data.
+    fn handle_count(&self) -> usize;
+    /// Address of the loaded data, if any. See `data_addr`.
+    fn loaded_addr(&self) -> Option<usize>;
+    /// Index of the config variant selected for the active profile. `None` is the
+    /// base config.
+    fn variant(&self) -> Option<usize> {
+        None
+    }
+    fn set_variant(&mut self, _variant: Option<usize>) {}
+    /// Measurements of the last load. Kept after the entry is unloaded.
+    fn load_stats(&self) -> Option<LoadStats> {
+        None
+    }
+}
+
+/// Entries whose loaded data is shared through `Asset` handles.
+pub trait LoadedEntry: DatabaseEntry {
+    type Data;
+
+    fn loaded(&self) -> &Option<Arc<Self::Data>>;
+    /// Stores data loaded from `fs` in `time`, recording its stats.
+    fn set_loaded(&mut self, data: Self::Data, time: Duration, fs: &dyn FileSystem);
+}
+
+/// Implements `DatabaseEntry` and `LoadedEntry` for an entry with the `cfg`,
+/// `layer`, `loaded` and `stats` fields of `ImageEntry`. Entries of categories with
+/// `variants` also have its `variant` field and load from the selected variant's
+/// path; the others load from the config field named by `path`.
+macro_rules! impl_entry {
+    ($entry:ty, $cfg:ty, $data:ty, $category:expr, variants) => {
+        impl DatabaseEntry for $entry {
+            impl_entry!(@common $cfg, $category);
+
+            fn from_cfg(cfg: Self::Config, layer: usize) -> Self {
+                Self {
+                    cfg,
+                    layer,
+                    variant: None,
+                    loaded: None,
+                    stats: None,
+                }
+            }
+
+            fn path(&self) -> &str {
+                let variant = self.variant.and_then(|i| self.cfg.variants().get(i)?.path());
+                variant.unwrap_or(&self.cfg.path)
+            }
+
+            fn variant(&self) -> Option<usize> {
+                self.variant
+            }
+
+            fn set_variant(&mut self, variant: Option<usize>) {
+                self.variant = variant;
+            }
+        }
+
+        impl_entry!(@loaded $entry, $data);
+    };
+    ($entry:ty, $cfg:ty, $data:ty, $category:expr, path: $path:ident) => {
+        impl DatabaseEntry for $entry {
+            impl_entry!(@common $cfg, $category);
+
+            fn from_cfg(cfg: Self::Config, layer: usize) -> Self {
+                Self {
+                    cfg,
+                    layer,
+                    loaded: None,
+                    stats: None,
+                }
+            }
+
+            fn path(&self) -> &str {
+                &self.cfg.$path
+            }
+        }
+
+        impl_entry!(@loaded $entry, $data);
+    };
+    (@common $cfg:ty, $category:expr) => {
+        type Config = $cfg;
+        const CATEGORY: AssetCategory = $category;
+
+        fn cfg(&self) -> &Self::Config {
+            &self.cfg
+        }
+
+        fn cfg_mut(&mut self) -> &mut Self::Config {
+            &mut self.cfg
+        }
+
+        fn layer(&self) -> usize {
+            self.layer
+        }
+
+        fn name(&self) -> &str {
+            &self.cfg.name
+        }
+
+        fn is_loaded(&self) -> bool {
+            self.loaded.is_some()
+        }
+
+        fn unload(&mut self) {
+            self.loaded = None;
+        }
+
+        fn byte_size(&self) -> usize {
+            self.loaded.as_ref().map(|l| l.byte_size()).unwrap_or(0)
+        }
+
+        fn handle_count(&self) -> usize {
+            self.loaded
+                .as_ref()
+                .map(|l| Arc::strong_count(l) - 1)
+                .unwrap_or(0)
+        }
+
+        fn loaded_addr(&self) -> Option<usize> {
+            self.loaded.as_ref().map(data_addr)
+        }
+
+        fn load_stats(&self) -> Option<LoadStats> {
+            self.stats
+        }
+    };
+    (@loaded $entry:ty, $data:ty) => {
+        impl LoadedEntry for $entry {
+            type Data = $data;
+
+            fn loaded(&self) -> &Option<Arc<Self::Data>> {
+                &self.loaded
+            }
+
+            fn set_loaded(&mut self, data: Self::Data, time: Duration, fs: &dyn FileSystem) {
+                self.stats = Some(LoadStats::new(fs, self.path(), time, data.byte_size()));
+                self.loaded = Some(Arc::new(data));
+            }
+        }
+    };
+}
+pub(crate) use impl_entry;
+
+/// A loading error for `entry` naming the file it failed on and why.
+fn entry_error(entry: &str, fs: &dyn FileSystem, path: &str, cause: impl std::fmt::Display) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: entry.to_string(),
+        path: format!("{}/{}", fs.root(), path),
+        cause: cause.to_string(),
+    })
+}
+
+/// Loads an RGBA8 image, tagging any failure with the entry it was loaded for.
+pub fn load_entry_image(
+    entry: &str,
+    fs: &dyn FileSystem,
+    path: &str,
+) -> Result<ImageLoadInfo<u8>, Error> {
+    load_image_rgba8(fs, path).map_err(|e| entry_error(entry, fs, path, error_cause(e)))
+}
 
 pub struct ImageEntry {
     pub cfg: ImageJSONEntry,
-    pub loaded: Option<ImageLoadInfo<u8>>,
+    pub layer: usize,
+    /// See `DatabaseEntry::variant`.
+    pub variant: Option<usize>,
+    pub loaded: Option<Arc<ImageLoadInfo<u8>>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
 }
 
 impl ImageEntry {
-    pub fn load(&mut self, base_path: &str) {
-        self.loaded =
-            Some(load_image_rgba8(&format!("{}/{}", base_path, self.cfg.path.as_str())).unwrap());
+    pub fn load(&mut self, fs: &dyn FileSystem) -> Result<(), Error> {
+        let (data, time) = timed(|| load_entry_image(&self.cfg.name, fs, self.path()))?;
+        self.set_loaded(data, time, fs);
+        Ok(())
+    }
+}
+
+impl_entry!(ImageEntry, ImageJSONEntry, ImageLoadInfo<u8>, AssetCategory::Image, variants);
+
+/// A loaded atlas image together with the sprites cut from it.
+pub struct Atlas {
+    pub image: ImageLoadInfo<u8>,
+    pub sprites: Vec<AtlasJSONSprite>,
+}
+
+impl Atlas {
+    pub fn sprite(&self, name: &str) -> Option<&AtlasJSONSprite> {
+        self.sprites.iter().find(|s| s.name == name)
+    }
+
+    pub fn sprite_by_id(&self, id: u32) -> Option<&AtlasJSONSprite> {
+        self.sprites.iter().find(|s| s.id == id)
     }
 
-    pub fn unload(&mut self) {
-        self.loaded = None;
+    pub fn byte_size(&self) -> usize {
+        self.image.byte_size() + self.sprites.len() * std::mem::size_of::<AtlasJSONSprite>()
     }
 }
 
+pub fn load_entry_atlas(cfg: &AtlasJSONEntry, fs: &dyn FileSystem) -> Result<Atlas, Error> {
+    Ok(Atlas {
+        image: load_entry_image(&cfg.name, fs, &cfg.path)?,
+        sprites: cfg.entries.clone().unwrap_or_default(),
+    })
+}
+
 pub struct AtlasEntry {
     pub cfg: AtlasJSONEntry,
-    pub loaded: Option<ImageLoadInfo<u8>>,
+    pub layer: usize,
+    /// See `DatabaseEntry::variant`.
+    pub variant: Option<usize>,
+    pub loaded: Option<Arc<Atlas>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
 }
 
 impl AtlasEntry {
-    pub fn load(&mut self, base_path: &str) {
-        self.loaded =
-            Some(load_image_rgba8(&format!("{}/{}", base_path, self.cfg.path.as_str())).unwrap());
+    /// The config with the selected variant applied.
+    pub fn resolved_cfg(&self) -> AtlasJSONEntry {
+        self.cfg.with_variant(self.variant)
+    }
+
+    pub fn load(&mut self, fs: &dyn FileSystem) -> Result<(), Error> {
+        let (data, time) = timed(|| load_entry_atlas(&self.resolved_cfg(), fs))?;
+        self.set_loaded(data, time, fs);
+        Ok(())
+    }
+
+    /// Every sprite declared for this atlas, as laid out in the selected variant.
+    pub fn sprites(&self) -> &[AtlasJSONSprite] {
+        let variant = self.variant.and_then(|i| self.cfg.variants.as_ref()?.get(i));
+        variant
+            .and_then(|v| v.entries.as_deref())
+            .or(self.cfg.entries.as_deref())
+            .unwrap_or(&[])
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<&AtlasJSONSprite> {
+        self.sprites().iter().find(|s| s.name == name)
+    }
+
+    pub fn sprite_by_id(&self, id: u32) -> Option<&AtlasJSONSprite> {
+        self.sprites().iter().find(|s| s.id == id)
     }
+}
+
+impl_entry!(AtlasEntry, AtlasJSONEntry, Atlas, AssetCategory::Atlas, variants);
 
-    pub fn unload(&mut self) {
-        self.loaded = None;
+/// Applies the per-mesh overrides from `cfg` to a freshly loaded model. Each
+/// override applies to the meshes named by its `mesh`, as they are named in the
+/// file, or else to the mesh at its position.
+pub fn apply_mesh_info(model: &mut Model, cfg: &GeometryJSONEntry) {
+    let file_names: Vec<String> = model.meshes.iter().map(|m| m.name.clone()).collect();
+    for mesh in model.meshes.iter_mut() {
+        mesh.render_mask = Some(cfg.render_mask.clone());
+    }
+
+    for (position, info) in cfg.meshes.iter().flatten().enumerate() {
+        for (index, mesh) in model.meshes.iter_mut().enumerate() {
+            let matches = match &info.mesh {
+                Some(name) => file_names[index] == *name,
+                None => index == position,
+            };
+            if !matches {
+                continue;
+            }
+
+            if let Some(name) = &info.name {
+                mesh.name = name.clone();
+            }
+            if let Some(material) = &info.material {
+                mesh.material.name = material.clone();
+            }
+            if let Some(mask) = &info.render_mask {
+                mesh.render_mask = Some(mask.clone());
+            }
+        }
+    }
+}
+
+/// Loads a glTF model for `cfg` and applies its mesh overrides. External buffers
+/// are resolved relative to the model file.
+pub fn load_entry_model(cfg: &GeometryJSONEntry, fs: &dyn FileSystem) -> Result<Model, Error> {
+    match load_gltf_model(fs, &cfg.path) {
+        Ok(mut model) => {
+            apply_mesh_info(&mut model, cfg);
+            Ok(model)
+        }
+        Err(e) => Err(entry_error(&cfg.name, fs, &cfg.path, error_cause(e))),
     }
 }
 
 pub struct GeometryEntry {
     pub cfg: GeometryJSONEntry,
-    pub loaded: Option<super::Model>,
+    pub layer: usize,
+    /// See `DatabaseEntry::variant`.
+    pub variant: Option<usize>,
+    pub loaded: Option<Arc<Model>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
 }
 
 impl GeometryEntry {
-    pub fn load(&mut self, base_path: &str) {
-        if self.loaded.is_none() {
-            self.loaded =
-                super::load_gltf_model(&format!("{}/{}", base_path, self.cfg.path.as_str()));
-        }
+    /// The config with the selected variant applied.
+    pub fn resolved_cfg(&self) -> GeometryJSONEntry {
+        self.cfg.with_variant(self.variant)
     }
 
-    pub fn unload(&mut self) {
-        self.loaded = None;
+    pub fn load(&mut self, fs: &dyn FileSystem) -> Result<(), Error> {
+        if self.loaded.is_none() {
+            let (data, time) = timed(|| load_entry_model(&self.resolved_cfg(), fs))?;
+            self.set_loaded(data, time, fs);
+        }
+        Ok(())
     }
 }
+
+impl_entry!(GeometryEntry, GeometryJSONEntry, Model, AssetCategory::Geometry, variants);
+
 pub struct TTFEntry {
     pub cfg: TTFJSONEntry,
-    pub loaded: Option<TTFont>,
+    pub layer: usize,
+    /// See `DatabaseEntry::variant`.
+    pub variant: Option<usize>,
+    pub loaded: Option<Arc<TTFont>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
+}
+
+/// Rasterizes `typeset` from the font file of `cfg`.
+pub fn load_entry_ttf(
+    cfg: &TTFJSONEntry,
+    fs: &dyn FileSystem,
+    typeset: &[char],
+) -> Result<TTFont, Error> {
+    TTFont::new(fs, &cfg.path, 1280, 1024, cfg.size as f32, typeset)
+        .map_err(|e| entry_error(&cfg.name, fs, &cfg.path, error_cause(e)))
 }
 
 impl TTFEntry {
-    pub fn load(&mut self, base_path: &str, typeset: &[char]) {
-        self.loaded = Some(TTFont::new(
-            &format!("{}/{}", base_path, self.cfg.path.as_str()),
-            1280,
-            1024,
-            self.cfg.size as f32,
-            typeset,
-        ));
-    }
-
-    pub fn unload(&mut self) {
-        self.loaded = None;
-    }
-}
-pub fn parse_atlasses(info: AtlasJSON) -> HashMap<String, AtlasEntry> {
-    let tup_vec: Vec<(String, AtlasEntry)> = info
-        .atlases
-        .into_iter()
-        .map(|a| {
-            (
-                a.name.clone(),
-                AtlasEntry {
-                    cfg: a.clone(),
-                    loaded: None,
-                },
-            )
-        })
-        .collect();
-
-    return tup_vec.into_iter().collect();
-}
-
-pub fn parse_images(info: ImageJSON) -> HashMap<String, ImageEntry> {
-    let tup_vec: Vec<(String, ImageEntry)> = info
-        .images
-        .into_iter()
-        .map(|a| {
-            (
-                a.name.clone(),
-                ImageEntry {
-                    cfg: a.clone(),
-                    loaded: None,
-                },
-            )
-        })
-        .collect();
-
-    return tup_vec.into_iter().collect();
-}
-
-pub fn parse_ttfs(info: TTFJSON) -> HashMap<String, TTFEntry> {
-    let tup_vec: Vec<(String, TTFEntry)> = info
-        .fonts
-        .into_iter()
-        .map(|a| {
-            (
-                a.name.clone(),
-                TTFEntry {
-                    cfg: a.clone(),
-                    loaded: None,
-                },
-            )
-        })
-        .collect();
+    /// The config with the selected variant applied.
+    pub fn resolved_cfg(&self) -> TTFJSONEntry {
+        self.cfg.with_variant(self.variant)
+    }
+
+    pub fn load(&mut self, fs: &dyn FileSystem, typeset: &[char]) -> Result<(), Error> {
+        let (data, time) = timed(|| load_entry_ttf(&self.resolved_cfg(), fs, typeset))?;
+        self.set_loaded(data, time, fs);
+        Ok(())
+    }
+}
+
+impl_entry!(TTFEntry, TTFJSONEntry, TTFont, AssetCategory::TTF, variants);
+
+/// Entries for the configs of one layer, in declaration order.
+pub fn parse_entries<E: DatabaseEntry>(cfgs: Vec<E::Config>, layer: usize) -> Vec<E> {
+    cfgs.into_iter().map(|cfg| E::from_cfg(cfg, layer)).collect()
+}
+
+pub fn parse_atlasses(info: AtlasJSON, layer: usize) -> Vec<AtlasEntry> {
+    parse_entries(info.atlases, layer)
+}
+
+pub fn parse_images(info: ImageJSON, layer: usize) -> Vec<ImageEntry> {
+    parse_entries(info.images, layer)
+}
+
+pub fn parse_ttfs(info: TTFJSON, layer: usize) -> Vec<TTFEntry> {
+    parse_entries(info.fonts, layer)
+}
 
-    return tup_vec.into_iter().collect();
+pub fn parse_geometry(info: GeometryJSON, layer: usize) -> Vec<GeometryEntry> {
+    parse_entries(info.models, layer)
 }
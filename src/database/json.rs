@@ -1,87 +1,580 @@
+use super::images::AssetKey;
+use super::schema::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Deserialize, Serialize, Clone)]
+/// An image file for profiles with every trait in `when`, e.g. an `@2x` path.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ImageVariantJSON {
+    pub when: Vec<String>,
+    pub path: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct ImageJSONEntry {
     pub name: String,
     pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+    /// Alternate sources for other profiles, see `VariantProfile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<ImageVariantJSON>>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct ImageJSON {
+    #[serde(default)]
+    pub version: u32,
     pub images: Vec<ImageJSONEntry>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct AtlasJSONSprite {
     pub name: String,
     pub id: u32,
+    #[schemars(with = "Rect2DSchema")]
     pub bounds: dashi::Rect2D,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct AtlasJSONAutoGen {
     pub name: String,
+    #[schemars(with = "Rect2DSchema")]
     pub bounds: dashi::Rect2D,
     pub stride: u32,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+/// An atlas image for profiles with every trait in `when`. Sprites default to the
+/// base entry's, which only fits images of the same size.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct AtlasVariantJSON {
+    pub when: Vec<String>,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entries: Option<Vec<AtlasJSONSprite>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct AtlasJSONEntry {
     pub name: String,
     pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub entries: Option<Vec<AtlasJSONSprite>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub auto_gen: Option<AtlasJSONAutoGen>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+    /// Alternate sources for other profiles, see `VariantProfile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<AtlasVariantJSON>>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct AtlasJSON {
+    #[serde(default)]
+    pub version: u32,
     pub atlases: Vec<AtlasJSONEntry>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+/// A font file or size for profiles with every trait in `when`. Unset fields keep
+/// the base entry's.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct TTFVariantJSON {
+    pub when: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct TTFJSONEntry {
     pub name: String,
     pub path: String,
     pub size: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub glyphs: Option<String>,
+    /// Also rasterize every character the strings of this locale use. Without
+    /// `glyphs`, only those characters and `runtime_glyphs` are rasterized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+    /// Alternate sources for other profiles, see `VariantProfile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<TTFVariantJSON>>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct TTFJSON {
+    #[serde(default)]
+    pub version: u32,
     pub fonts: Vec<TTFJSONEntry>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct MeshInfo {
+    /// Name of the glTF mesh the overrides apply to. Overrides without one apply
+    /// to the mesh at the same position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub material: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub render_mask: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+/// A model file for profiles with every trait in `when`, e.g. a decimated mesh.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct GeometryVariantJSON {
+    pub when: Vec<String>,
+    pub path: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct GeometryJSONEntry {
     pub name: String,
     pub path: String,
     pub render_mask: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub meshes: Option<Vec<MeshInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+    /// Alternate sources for other profiles, see `VariantProfile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<GeometryVariantJSON>>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct GeometryJSON {
-    pub models: Vec<GeometryJSONEntry>, }
+    #[serde(default)]
+    pub version: u32,
+    pub models: Vec<GeometryJSONEntry>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ParticleAnimationJSON {
+    pub name: String,
+    pub id: u32,
+    pub time_per_frame_ms: f64,
+    /// Frames of the animation, in order, as rects on the particle sheet.
+    #[schemars(with = "Vec<Rect2DSchema>")]
+    pub sprites: Vec<dashi::Rect2D>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ParticleJSONEntry {
+    pub name: String,
+    pub id: u32,
+    pub image_path: String,
+    pub animations: Vec<ParticleAnimationJSON>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ParticleJSON {
+    #[serde(default)]
+    pub version: u32,
+    pub particles: Vec<ParticleJSONEntry>,
+}
+
+/// A loop region in sample frames. Without `end`, the loop runs to the end of
+/// the clip.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, JsonSchema)]
+pub struct LoopPointsJSON {
+    pub start: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct AudioJSONEntry {
+    pub name: String,
+    /// A `.wav` or `.ogg` (Vorbis) file.
+    pub path: String,
+    /// Keep the encoded file and decode while playing instead of decoding it all
+    /// on load. Meant for music and other long clips.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub streaming: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_points: Option<LoopPointsJSON>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct AudioJSON {
+    #[serde(default)]
+    pub version: u32,
+    pub audio: Vec<AudioJSONEntry>,
+}
+
+/// The string table of one locale, e.g. `fr-CA`. Strings may contain
+/// `{placeholder}`s; `{{` and `}}` stand for literal braces.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct LocaleJSON {
+    pub locale: String,
+    pub strings: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct LocalizationJSON {
+    #[serde(default)]
+    pub version: u32,
+    /// Last locale of every fallback chain.
+    #[serde(default = "default_locale")]
+    pub default_locale: String,
+    pub locales: Vec<LocaleJSON>,
+}
+
+fn default_locale() -> String {
+    "en".to_string()
+}
 
-#[derive(Deserialize, Serialize, Clone)]
+/// An entry of a category the database doesn't know, e.g. a dialogue tree.
+#[derive(Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct CustomJSONEntry {
+    pub name: String,
+    /// Key of the category in `DatabaseJSON::categories`, filled in on load.
+    #[serde(skip)]
+    pub category: String,
+    /// Source file, if the entry has one. Entries may also keep all their data
+    /// inline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Entries this one needs loaded alongside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<AssetKey>>,
+    /// Every other field, left for the category's loader.
+    #[serde(flatten)]
+    pub data: BTreeMap<String, serde_json::Value>,
+}
+
+/// A config named in `DatabaseJSON::categories`.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+pub struct CustomJSON {
+    #[serde(default)]
+    pub version: u32,
+    pub entries: Vec<CustomJSONEntry>,
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct MaterialJSON {
     pub materials: Vec<super::geometry::Material>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 pub struct DatabaseJSON {
+    /// Layout version of this and the other config files, see
+    /// `migrate::CONFIG_VERSION`. Older files are upgraded when read.
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub image_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub atlas_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub geometry_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub ttf_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub particle_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub localization_cfg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub render_graph_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub shader_path: Option<String>,
+    /// Configs of categories the crate doesn't know, by category name. Their
+    /// entries are loaded by the `AssetLoader` registered under that name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub categories: BTreeMap<String, String>,
+}
+
+/// The parts of a variant that selection and validation look at.
+pub trait VariantConfig {
+    /// Traits the active profile needs for this variant to apply.
+    fn when(&self) -> &[String];
+    /// Source file of the variant, if it replaces the entry's.
+    fn path(&self) -> Option<&str>;
+}
+
+impl VariantConfig for ImageVariantJSON {
+    fn when(&self) -> &[String] {
+        &self.when
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+}
+
+impl VariantConfig for AtlasVariantJSON {
+    fn when(&self) -> &[String] {
+        &self.when
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+}
+
+impl VariantConfig for TTFVariantJSON {
+    fn when(&self) -> &[String] {
+        &self.when
+    }
+
+    fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+}
+
+impl VariantConfig for GeometryVariantJSON {
+    fn when(&self) -> &[String] {
+        &self.when
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+}
+
+fn variant_list<V: VariantConfig>(variants: &Option<Vec<V>>) -> Vec<&dyn VariantConfig> {
+    variants
+        .iter()
+        .flatten()
+        .map(|v| v as &dyn VariantConfig)
+        .collect()
+}
+
+impl ImageJSONEntry {
+    /// This config with `variant` applied. `None` is the base entry.
+    pub fn with_variant(&self, variant: Option<usize>) -> Self {
+        let mut cfg = self.clone();
+        if let Some(v) = variant.and_then(|i| self.variants.as_ref()?.get(i)) {
+            cfg.path = v.path.clone();
+        }
+        cfg
+    }
+}
+
+impl AtlasJSONEntry {
+    /// This config with `variant` applied. `None` is the base entry.
+    pub fn with_variant(&self, variant: Option<usize>) -> Self {
+        let mut cfg = self.clone();
+        if let Some(v) = variant.and_then(|i| self.variants.as_ref()?.get(i)) {
+            cfg.path = v.path.clone();
+            if v.entries.is_some() {
+                cfg.entries = v.entries.clone();
+            }
+        }
+        cfg
+    }
+}
+
+impl TTFJSONEntry {
+    /// This config with `variant` applied. `None` is the base entry.
+    pub fn with_variant(&self, variant: Option<usize>) -> Self {
+        let mut cfg = self.clone();
+        if let Some(v) = variant.and_then(|i| self.variants.as_ref()?.get(i)) {
+            if let Some(path) = &v.path {
+                cfg.path = path.clone();
+            }
+            if let Some(size) = v.size {
+                cfg.size = size;
+            }
+        }
+        cfg
+    }
+}
+
+impl GeometryJSONEntry {
+    /// This config with `variant` applied. `None` is the base entry.
+    pub fn with_variant(&self, variant: Option<usize>) -> Self {
+        let mut cfg = self.clone();
+        if let Some(v) = variant.and_then(|i| self.variants.as_ref()?.get(i)) {
+            cfg.path = v.path.clone();
+        }
+        cfg
+    }
+}
+
+/// Name access shared by the per-category entry configs.
+pub trait EntryConfig {
+    fn name(&self) -> &str;
+    fn set_name(&mut self, name: &str);
+    fn tags(&self) -> &[String];
+    fn depends_on(&self) -> &[AssetKey];
+    /// Declared variants, in order. Categories without variants have none.
+    fn variants(&self) -> Vec<&dyn VariantConfig> {
+        Vec::new()
+    }
+}
+
+impl EntryConfig for ImageJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
+    fn variants(&self) -> Vec<&dyn VariantConfig> {
+        variant_list(&self.variants)
+    }
+}
+
+impl EntryConfig for AtlasJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
+    fn variants(&self) -> Vec<&dyn VariantConfig> {
+        variant_list(&self.variants)
+    }
+}
+
+impl EntryConfig for TTFJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
+    fn variants(&self) -> Vec<&dyn VariantConfig> {
+        variant_list(&self.variants)
+    }
+}
+
+impl EntryConfig for GeometryJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
+    fn variants(&self) -> Vec<&dyn VariantConfig> {
+        variant_list(&self.variants)
+    }
+}
+
+impl EntryConfig for ParticleJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+}
+
+impl EntryConfig for AudioJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+}
+
+impl EntryConfig for CustomJSONEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    fn depends_on(&self) -> &[AssetKey] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
 }
@@ -1,14 +1,29 @@
 use super::error::*;
+use super::vfs::*;
 pub struct ImageLoadInfo<T> {
    pub size: [u32; 2],
    pub format: dashi::Format,
    pub bytes: Vec<T>,
 }
 
-pub fn load_image_rgba8(path: &str) -> Result<ImageLoadInfo<u8>, Error>{
-    println!("Loading {}", path);
-    let img = image::open(&path)?;
-    
+impl<T> ImageLoadInfo<T> {
+    /// Resident size of the decoded pixels in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.bytes.len() * std::mem::size_of::<T>()
+    }
+}
+
+pub fn load_image_rgba8(fs: &dyn FileSystem, path: &str) -> Result<ImageLoadInfo<u8>, Error>{
+    load_image_rgba8_from_memory(&fs.read(path)?)
+}
+
+/// Decodes an encoded image (PNG, JPEG, ...) held in memory.
+pub fn load_image_rgba8_from_memory(bytes: &[u8]) -> Result<ImageLoadInfo<u8>, Error> {
+    let img = image::load_from_memory(bytes)?;
+    Ok(to_rgba8_load_info(img))
+}
+
+fn to_rgba8_load_info(img: image::DynamicImage) -> ImageLoadInfo<u8> {
     // Convert the image to RGBA8 format
     let rgba_image = img.to_rgba8();
 
@@ -18,9 +33,9 @@ pub fn load_image_rgba8(path: &str) -> Result<ImageLoadInfo<u8>, Error>{
     let (width, height) = rgba_image.dimensions();
     let bytes = rgba_image.into_raw();
     assert!((width*height*4) as usize == bytes.len());
-    Ok(ImageLoadInfo::<u8> {
+    ImageLoadInfo::<u8> {
         size: [width, height],
         format: dashi::Format::RGBA8,
         bytes,
-    })
+    }
 }
@@ -0,0 +1,222 @@
+use super::error::*;
+use super::images::AssetKey;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Observable state of a background load.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadStatus {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
+enum LoadSlot<T> {
+    Pending,
+    Ready(T, Duration),
+    // The result was moved into the database; the load still counts as ready.
+    Taken(Option<Duration>),
+    Failed(String),
+}
+
+/// Pollable handle to a load running on the loader pool.
+pub struct LoadHandle<T> {
+    name: String,
+    slot: Arc<(Mutex<LoadSlot<T>>, Condvar)>,
+}
+
+impl<T> Clone for LoadHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> LoadHandle<T> {
+    fn new(name: &str, slot: LoadSlot<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            slot: Arc::new((Mutex::new(slot), Condvar::new())),
+        }
+    }
+
+    /// A handle for an entry that is already resident.
+    pub(crate) fn ready(name: &str) -> Self {
+        LoadHandle::new(name, LoadSlot::Taken(None))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> LoadStatus {
+        match &*self.slot.0.lock().unwrap() {
+            LoadSlot::Pending => LoadStatus::Pending,
+            LoadSlot::Ready(..) | LoadSlot::Taken(_) => LoadStatus::Ready,
+            LoadSlot::Failed(msg) => LoadStatus::Failed(msg.clone()),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status() == LoadStatus::Pending
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status() == LoadStatus::Ready
+    }
+
+    /// Blocks the calling thread until the load has finished.
+    pub fn wait(&self) -> LoadStatus {
+        let (lock, cvar) = &*self.slot;
+        let mut slot = lock.lock().unwrap();
+        while let LoadSlot::Pending = *slot {
+            slot = cvar.wait(slot).unwrap();
+        }
+        drop(slot);
+        self.status()
+    }
+
+    /// How long the loader spent producing the result. `None` until the load has
+    /// succeeded, and for entries that were already resident when requested.
+    pub fn decode_time(&self) -> Option<Duration> {
+        match &*self.slot.0.lock().unwrap() {
+            LoadSlot::Ready(_, time) => Some(*time),
+            LoadSlot::Taken(time) => *time,
+            LoadSlot::Pending | LoadSlot::Failed(_) => None,
+        }
+    }
+
+    fn finish(&self, res: Result<T, Error>, time: Duration) {
+        let (lock, cvar) = &*self.slot;
+        *lock.lock().unwrap() = match res {
+            Ok(data) => LoadSlot::Ready(data, time),
+            Err(err) => LoadSlot::Failed(err.to_string()),
+        };
+        cvar.notify_all();
+    }
+
+    /// Moves a finished result out of the handle. Returns None while still pending
+    /// or once the result has already been taken.
+    pub(crate) fn take(&self) -> Option<Result<T, Error>> {
+        let mut slot = self.slot.0.lock().unwrap();
+        match &*slot {
+            LoadSlot::Pending | LoadSlot::Taken(_) => None,
+            LoadSlot::Failed(msg) => Some(Err(Error::LoadingError(LoadingError {
+                entry: self.name.clone(),
+                path: String::new(),
+                cause: msg.clone(),
+            }))),
+            LoadSlot::Ready(_, time) => {
+                let taken = LoadSlot::Taken(Some(*time));
+                match std::mem::replace(&mut *slot, taken) {
+                    LoadSlot::Ready(data, _) => Some(Ok(data)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Background loads that `Database::poll_loads` found finished.
+#[derive(Debug, Default)]
+pub struct PolledLoads {
+    /// Entries whose data was moved into the database.
+    pub ready: Vec<AssetKey>,
+    /// Entries whose load failed, with the reason.
+    pub failed: Vec<(AssetKey, Error)>,
+}
+
+/// Fixed-size pool of worker threads that decode assets off the calling thread.
+pub struct LoaderPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl LoaderPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                std::thread::Builder::new()
+                    .name(format!("remouillage-loader-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn loader thread!")
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues `f` on the pool and returns a handle that can be polled for its result.
+    pub fn submit<T, F>(&self, name: &str, f: F) -> LoadHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, Error> + Send + 'static,
+    {
+        let handle = LoadHandle::new(name, LoadSlot::Pending);
+        let slot = handle.clone();
+        let job: Job = Box::new(move || {
+            // A panicking decoder must not take the worker down with it.
+            let start = Instant::now();
+            let res = match catch_unwind(AssertUnwindSafe(f)) {
+                Ok(res) => res,
+                Err(_) => Err(Error::LoadingError(LoadingError {
+                    entry: slot.name.clone(),
+                    path: String::new(),
+                    cause: "loader panicked".to_string(),
+                })),
+            };
+            slot.finish(res, start.elapsed());
+        });
+
+        if let Some(sender) = &self.sender {
+            if sender.send(job).is_err() {
+                handle.finish(Err(Error::SlotError()), Duration::ZERO);
+            }
+        }
+
+        handle
+    }
+}
+
+impl Default for LoaderPool {
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        LoaderPool::new(threads)
+    }
+}
+
+impl Drop for LoaderPool {
+    fn drop(&mut self) {
+        // Closing the channel lets every worker fall out of its loop.
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use super::error::*;
+use super::json::*;
+use std::collections::{BTreeSet, HashMap};
+
+/// The string tables of every locale, merged across layers.
+#[derive(Debug, Clone, Default)]
+pub struct Localization {
+    default_locale: String,
+    tables: HashMap<String, HashMap<String, String>>,
+}
+
+enum Piece<'a> {
+    Char(char),
+    Placeholder(&'a str),
+}
+
+/// Splits `text` into literal characters and placeholder names, in order. `{{`
+/// and `}}` are literal braces, as is a `{` that is never closed.
+fn scan_placeholders<'a>(text: &'a str, mut piece: impl FnMut(Piece<'a>)) {
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, n)| n);
+        match c {
+            '{' | '}' if next == Some(c) => {
+                chars.next();
+                piece(Piece::Char(c));
+            }
+            '{' => match text[start + 1..].find('}') {
+                Some(len) => {
+                    let end = start + 1 + len;
+                    piece(Piece::Placeholder(&text[start + 1..end]));
+                    while chars.next_if(|&(i, _)| i <= end).is_some() {}
+                }
+                None => piece(Piece::Char(c)),
+            },
+            _ => piece(Piece::Char(c)),
+        }
+    }
+}
+
+/// Replaces every `{name}` in `text` with its value from `args`. Unknown
+/// placeholders are kept as they are.
+pub fn format_placeholders(text: &str, args: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    scan_placeholders(text, |piece| match piece {
+        Piece::Char(c) => out.push(c),
+        Piece::Placeholder(name) => match args.iter().find(|(k, _)| *k == name) {
+            Some((_, value)) => out.push_str(value),
+            None => {
+                out.push('{');
+                out.push_str(name);
+                out.push('}');
+            }
+        },
+    });
+    out
+}
+
+impl Localization {
+    /// Merges the configs of every layer, base first. Strings of higher layers
+    /// replace those of lower ones key by key; the highest layer's default locale
+    /// wins.
+    pub fn from_configs(configs: impl IntoIterator<Item = LocalizationJSON>) -> Self {
+        let mut localization = Localization::default();
+        for cfg in configs {
+            localization.default_locale = cfg.default_locale;
+            for locale in cfg.locales {
+                localization
+                    .tables
+                    .entry(locale.locale)
+                    .or_default()
+                    .extend(locale.strings);
+            }
+        }
+        localization
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default_locale
+    }
+
+    /// Every locale that has a string table, sorted.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<&str> = self.tables.keys().map(|l| l.as_str()).collect();
+        locales.sort();
+        locales
+    }
+
+    /// Locales looked up for `locale`, most specific first: `fr-CA` gives `fr-CA`,
+    /// `fr` and then the default locale.
+    pub fn fallback_chain(&self, locale: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = locale;
+        loop {
+            chain.push(current.to_string());
+            match current.rfind(['-', '_']) {
+                Some(end) => current = &current[..end],
+                None => break,
+            }
+        }
+        if !self.default_locale.is_empty() && !chain.contains(&self.default_locale) {
+            chain.push(self.default_locale.clone());
+        }
+        chain
+    }
+
+    /// The string `key` of the first locale in the fallback chain that has it.
+    pub fn get(&self, locale: &str, key: &str) -> Option<&str> {
+        self.get_in(&self.fallback_chain(locale), key)
+    }
+
+    fn get_in(&self, chain: &[String], key: &str) -> Option<&str> {
+        chain
+            .iter()
+            .find_map(|l| self.tables.get(l)?.get(key))
+            .map(|s| s.as_str())
+    }
+
+    /// Looks up `key` like `get` and fills in its placeholders from `args`.
+    pub fn format(&self, locale: &str, key: &str, args: &[(&str, &str)]) -> Option<String> {
+        self.get(locale, key).map(|text| format_placeholders(text, args))
+    }
+
+    /// Every character that the strings shown for `locale` contain, sorted.
+    /// Placeholders are left out since their values aren't known here; see
+    /// `runtime_glyphs`.
+    pub fn glyphs(&self, locale: &str) -> Vec<char> {
+        let chain = self.fallback_chain(locale);
+        let keys: BTreeSet<&String> = chain
+            .iter()
+            .filter_map(|l| self.tables.get(l))
+            .flat_map(|t| t.keys())
+            .collect();
+
+        let mut glyphs = BTreeSet::new();
+        for key in keys {
+            if let Some(text) = self.get_in(&chain, key) {
+                scan_placeholders(text, |piece| {
+                    if let Piece::Char(c) = piece {
+                        glyphs.insert(c);
+                    }
+                });
+            }
+        }
+        glyphs.into_iter().collect()
+    }
+}
+
+/// Characters a locale font gets besides those of its strings, so numbers and
+/// punctuation filled into placeholders at runtime can be shown.
+pub fn runtime_glyphs() -> impl Iterator<Item = char> {
+    (' '..='~').filter(|c| !c.is_ascii_alphabetic())
+}
+
+fn missing_string(locale: &str, key: &str) -> Error {
+    Error::LookupError(LookupError {
+        entry: format!("{}:{}", locale, key),
+    })
+}
+
+impl super::Database {
+    pub fn localization(&self) -> &Localization {
+        &self.localization
+    }
+
+    /// The string `key` for `locale`, falling back along `fallback_chain`.
+    pub fn localize(&self, locale: &str, key: &str) -> Result<&str, Error> {
+        self.localization
+            .get(locale, key)
+            .ok_or_else(|| missing_string(locale, key))
+    }
+
+    /// The string `key` for `locale` with its `{placeholder}`s filled in from `args`.
+    pub fn localize_format(&self, locale: &str, key: &str, args: &[(&str, &str)]) -> Result<String, Error> {
+        self.localization
+            .format(locale, key, args)
+            .ok_or_else(|| missing_string(locale, key))
+    }
+
+    /// Characters used by the strings of `locale`, for a font's glyph set.
+    pub fn locale_glyphs(&self, locale: &str) -> Vec<char> {
+        self.localization.glyphs(locale)
+    }
+}
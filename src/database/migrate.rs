@@ -0,0 +1,382 @@
+use super::authoring::write_config;
+use super::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Layout version written into every config file. Files without a `version`
+/// field are version 0.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigKind {
+    Database,
+    Image,
+    Atlas,
+    TTF,
+    Geometry,
+    Particle,
+    Audio,
+    Localization,
+    RenderGraph,
+    Custom,
+}
+
+/// A config struct that is read through the migration chain.
+pub trait VersionedConfig: DeserializeOwned {
+    const KIND: ConfigKind;
+}
+
+impl VersionedConfig for DatabaseJSON {
+    const KIND: ConfigKind = ConfigKind::Database;
+}
+
+impl VersionedConfig for ImageJSON {
+    const KIND: ConfigKind = ConfigKind::Image;
+}
+
+impl VersionedConfig for AtlasJSON {
+    const KIND: ConfigKind = ConfigKind::Atlas;
+}
+
+impl VersionedConfig for TTFJSON {
+    const KIND: ConfigKind = ConfigKind::TTF;
+}
+
+impl VersionedConfig for GeometryJSON {
+    const KIND: ConfigKind = ConfigKind::Geometry;
+}
+
+impl VersionedConfig for ParticleJSON {
+    const KIND: ConfigKind = ConfigKind::Particle;
+}
+
+impl VersionedConfig for AudioJSON {
+    const KIND: ConfigKind = ConfigKind::Audio;
+}
+
+impl VersionedConfig for LocalizationJSON {
+    const KIND: ConfigKind = ConfigKind::Localization;
+}
+
+impl VersionedConfig for RenderGraph {
+    const KIND: ConfigKind = ConfigKind::RenderGraph;
+}
+
+impl VersionedConfig for CustomJSON {
+    const KIND: ConfigKind = ConfigKind::Custom;
+}
+
+/// One upgrade step that was applied to a config file while reading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationRecord {
+    /// Root of the filesystem the file was read from.
+    pub root: String,
+    pub path: String,
+    pub kind: ConfigKind,
+    pub from: u32,
+    pub to: u32,
+    pub description: &'static str,
+}
+
+impl fmt::Display for MigrationRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}: v{} -> v{}: {}",
+            self.root, self.path, self.from, self.to, self.description
+        )
+    }
+}
+
+/// Upgrades a config of `kind` from version `from` to `from + 1`. The filesystem
+/// is the one the config was read from.
+struct Migration {
+    kind: ConfigKind,
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut Value),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        kind: ConfigKind::Image,
+        from: 0,
+        description: "rename sprites/image_path to images/path",
+        apply: image_v0,
+    },
+    Migration {
+        kind: ConfigKind::Atlas,
+        from: 0,
+        description: "rename sprite_sheets/image_path/sprites to atlases/path/entries",
+        apply: atlas_v0,
+    },
+    Migration {
+        kind: ConfigKind::Geometry,
+        from: 0,
+        description: "wrap a bare model list into models",
+        apply: geometry_v0,
+    },
+];
+
+fn rename_key(object: &mut Map<String, Value>, from: &str, to: &str) {
+    if object.contains_key(to) {
+        return;
+    }
+    if let Some(value) = object.remove(from) {
+        object.insert(to.to_string(), value);
+    }
+}
+
+/// Turns a bare list into `{ key: list }` and renames the legacy list key `from`.
+fn upgrade_list(value: &mut Value, from: &str, key: &str) {
+    if value.is_array() {
+        *value = Value::Object(Map::from_iter([(key.to_string(), value.take())]));
+    }
+    if let Some(object) = value.as_object_mut() {
+        rename_key(object, from, key);
+    }
+}
+
+/// Applies `rename` to every object in the list `key`.
+fn for_each_entry(value: &mut Value, key: &str, rename: impl Fn(&mut Map<String, Value>)) {
+    if let Some(entries) = value.get_mut(key).and_then(|v| v.as_array_mut()) {
+        entries.iter_mut().filter_map(|e| e.as_object_mut()).for_each(rename);
+    }
+}
+
+fn image_v0(value: &mut Value) {
+    upgrade_list(value, "sprites", "images");
+    for_each_entry(value, "images", |e| rename_key(e, "image_path", "path"));
+}
+
+fn atlas_v0(value: &mut Value) {
+    upgrade_list(value, "sprite_sheets", "atlases");
+    for_each_entry(value, "atlases", |e| {
+        rename_key(e, "image_path", "path");
+        rename_key(e, "sprites", "entries");
+    });
+}
+
+fn geometry_v0(value: &mut Value) {
+    upgrade_list(value, "models", "models");
+}
+
+fn config_version(value: &Value) -> Result<u32, String> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("invalid config version {}", version)),
+    }
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "config version {} is newer than the supported version {}",
+            version, CONFIG_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn migration(kind: ConfigKind, from: u32) -> Option<&'static Migration> {
+    MIGRATIONS.iter().find(|m| m.kind == kind && m.from == from)
+}
+
+/// The steps from `version` to `CONFIG_VERSION`, with what each one does.
+fn steps(kind: ConfigKind, version: u32) -> Vec<(u32, &'static str)> {
+    (version..CONFIG_VERSION)
+        .map(|from| (from, migration(kind, from).map(|m| m.description).unwrap_or("add version field")))
+        .collect()
+}
+
+/// `value` without its version field, to tell whether migrating changed more.
+fn layout(value: &Value) -> Value {
+    let mut value = value.clone();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("version");
+    }
+    value
+}
+
+/// Runs the migrations of `kind` on `value` until it is at `CONFIG_VERSION`.
+/// Returns the versions that were upgraded from, with what each step did.
+pub fn migrate_value(kind: ConfigKind, value: &mut Value) -> Result<Vec<(u32, &'static str)>, String> {
+    let version = config_version(value)?;
+    check_version(version)?;
+
+    let applied = steps(kind, version);
+    for (from, _) in &applied {
+        if let Some(step) = migration(kind, *from) {
+            (step.apply)(value);
+        }
+    }
+
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    }
+    Ok(applied)
+}
+
+/// A loading error for the config `path`, which is its own entry.
+fn config_error(fs: &dyn FileSystem, path: &str, cause: String) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: path.to_string(),
+        path: format!("{}/{}", fs.root(), path),
+        cause,
+    })
+}
+
+/// Replaces the records of the file `path` in `log` with the steps `applied`
+/// just took, so re-reading a file doesn't pile up records.
+fn record_migrations(
+    log: &mut Vec<MigrationRecord>,
+    fs: &dyn FileSystem,
+    path: &str,
+    kind: ConfigKind,
+    applied: Vec<(u32, &'static str)>,
+) {
+    let root = fs.root();
+    log.retain(|r| r.root != root || r.path != path);
+    log.extend(applied.into_iter().map(|(from, description)| MigrationRecord {
+        root: root.to_string(),
+        path: path.to_string(),
+        kind,
+        from,
+        to: from + 1,
+        description,
+    }));
+}
+
+/// Parses `text`, the contents of the config `path`, in the format its extension
+/// names, upgrading older layouts to the current structs. The steps applied
+/// replace any earlier records of the file in `log`.
+///
+/// Configs already in the current layout are deserialized straight from the
+/// text, so errors point at their line and column. Configs the migrations
+/// reshaped go through the migrated value; for RON that value loses enum variant
+/// names, which the older layouts don't use.
+pub fn parse_versioned<T: VersionedConfig>(
+    fs: &dyn FileSystem,
+    path: &str,
+    text: &str,
+    log: &mut Vec<MigrationRecord>,
+) -> Result<T, String> {
+    parse_versioned_checked(fs, path, text, log, &mut Vec::new())
+}
+
+/// Like `parse_versioned`, also adding the path of every key the current layout
+/// has no field for to `unknown`. Paths refer to the migrated layout.
+pub fn parse_versioned_checked<T: VersionedConfig>(
+    fs: &dyn FileSystem,
+    path: &str,
+    text: &str,
+    log: &mut Vec<MigrationRecord>,
+    unknown: &mut Vec<String>,
+) -> Result<T, String> {
+    let format = ConfigFormat::from_path(path);
+    let mut value = format.to_value(text)?;
+    let before = layout(&value);
+    let applied = migrate_value(T::KIND, &mut value)?;
+
+    let cfg = if layout(&value) == before {
+        format.parse_checked(text, unknown)?
+    } else {
+        from_value_checked(value, unknown).map_err(|e| format!("invalid {}: {}", format.name(), e))?
+    };
+
+    record_migrations(log, fs, path, T::KIND, applied);
+    Ok(cfg)
+}
+
+/// Reads the config `path` through `parse_versioned`.
+pub fn read_versioned<T: VersionedConfig>(
+    fs: &dyn FileSystem,
+    path: &str,
+    log: &mut Vec<MigrationRecord>,
+) -> Result<T, Error> {
+    let text = fs.read_to_string(path)?;
+    parse_versioned(fs, path, &text, log).map_err(|e| config_error(fs, path, e))
+}
+
+/// Rewrites `path` in the current layout if reading it needs any migration. The
+/// migrated value is written as is, so keys the structs don't know survive. RON
+/// has no lossless value form and is rewritten from the parsed config instead.
+fn upgrade_file<T: VersionedConfig + Serialize>(
+    fs: &dyn FileSystem,
+    path: &str,
+    log: &mut Vec<MigrationRecord>,
+) -> Result<(), Error> {
+    let format = ConfigFormat::from_path(path);
+    let text = fs.read_to_string(path)?;
+    let version = format.version(&text).map_err(|e| config_error(fs, path, e))?;
+    if version == CONFIG_VERSION {
+        return Ok(());
+    }
+
+    if !format.has_value_form() {
+        let cfg: T = read_versioned(fs, path, log)?;
+        return write_config(fs, path, &cfg);
+    }
+
+    let mut value = format.to_value(&text).map_err(|e| config_error(fs, path, e))?;
+    let applied = migrate_value(T::KIND, &mut value).map_err(|e| config_error(fs, path, e))?;
+    // Only write what reads back.
+    T::deserialize(&value).map_err(|e| config_error(fs, path, format!("invalid {}: {}", format.name(), e)))?;
+    write_config(fs, path, &value)?;
+    record_migrations(log, fs, path, T::KIND, applied);
+    Ok(())
+}
+
+impl Database {
+    /// Migrations the configs needed when they were last read, oldest first.
+    pub fn migrations(&self) -> &[MigrationRecord] {
+        &self.migrations
+    }
+
+    /// Write-back mode: rewrites every config of every layer that is older than
+    /// `CONFIG_VERSION` in the current layout, so later loads need no migration.
+    /// Returns the steps that were written.
+    pub fn upgrade_configs(&mut self) -> Result<Vec<MigrationRecord>, Error> {
+        let mut log = Vec::new();
+        for (fs, info) in self.layers.iter().zip(&self.infos) {
+            let fs = &**fs;
+            upgrade_file::<DatabaseJSON>(fs, database_config(fs)?, &mut log)?;
+            if let Some(path) = &info.image_cfg {
+                upgrade_file::<ImageJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.atlas_cfg {
+                upgrade_file::<AtlasJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.ttf_cfg {
+                upgrade_file::<TTFJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.geometry_cfg {
+                upgrade_file::<GeometryJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.particle_cfg {
+                upgrade_file::<ParticleJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.audio_cfg {
+                upgrade_file::<AudioJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.localization_cfg {
+                upgrade_file::<LocalizationJSON>(fs, path, &mut log)?;
+            }
+            if let Some(path) = &info.render_graph_path {
+                upgrade_file::<RenderGraph>(fs, path, &mut log)?;
+            }
+            for path in info.categories.values() {
+                upgrade_file::<CustomJSON>(fs, path, &mut log)?;
+            }
+        }
+
+        // Our own writes aren't changes for `poll_changes` to pick up.
+        self.watch_configs()?;
+        Ok(log)
+    }
+}
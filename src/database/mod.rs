@@ -2,165 +2,2773 @@ pub mod error;
 pub mod json;
 pub mod geometry;
 pub mod load_funcs;
-mod images;
+pub mod images;
 use std::collections::HashMap;
-use std::fs;
 use json::*;
 use error::*;
 use geometry::*;
-use images::*;
+pub use images::*;
+use load_funcs::*;
 pub mod font;
 pub use font::*;
+pub mod loader;
+pub use loader::*;
+pub mod watch;
+pub use watch::*;
+pub mod budget;
+pub use budget::*;
+pub mod handle;
+pub use handle::*;
+pub mod pack;
+pub use pack::*;
+pub mod vfs;
+pub use vfs::*;
+pub mod validate;
+pub use validate::*;
+pub mod authoring;
+pub mod query;
+pub use query::*;
+pub mod id;
+pub use id::*;
+pub mod deps;
+pub use deps::*;
+pub mod particles;
+pub use particles::*;
+pub mod shaders;
+pub use shaders::*;
+pub mod audio;
+pub use audio::*;
+pub mod stats;
+pub use stats::*;
+pub mod render_graph;
+pub use render_graph::*;
+pub mod migrate;
+pub use migrate::*;
+pub mod schema;
+pub use schema::{config_schemas, export_schemas, vscode_schema_settings, ConfigSchema};
+pub mod format;
+pub use format::*;
+pub mod variant;
+pub use variant::*;
+pub mod localization;
+pub use localization::*;
+pub mod custom;
+pub use custom::{AssetLoader, CustomEntry};
+use custom::*;
+use std::sync::Arc;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
 
 pub struct Database {
-    base_path: String,
-    images: HashMap<String, ImageEntry>,
-    atlases: HashMap<String, AtlasEntry>,
-//    geometry: HashMap<String, GeometryEntry>,
-    ttfs: HashMap<String, TTFEntry>,
+    layers: Vec<Arc<dyn FileSystem>>,
+    infos: Vec<DatabaseJSON>,
+    images: EntryMap<ImageEntry>,
+    atlases: EntryMap<AtlasEntry>,
+    geometry: EntryMap<GeometryEntry>,
+    ttfs: EntryMap<TTFEntry>,
+    particles: EntryMap<ParticleEntry>,
+    audio: EntryMap<AudioEntry>,
     particle_cfg: String,
+    localization: Localization,
+    custom: EntryMap<CustomEntry>,
+    loaders: HashMap<String, Box<dyn ErasedLoader>>,
+    shaders: HashMap<String, ShaderEntry>,
+    migrations: Vec<MigrationRecord>,
+    loader: Option<LoaderPool>,
+    pending_images: HashMap<String, LoadHandle<ImageLoadInfo<u8>>>,
+    pending_atlases: HashMap<String, LoadHandle<Atlas>>,
+    pending_ttfs: HashMap<String, LoadHandle<TTFont>>,
+    pending_models: HashMap<String, LoadHandle<Model>>,
+    config_watchers: Vec<FileWatcher>,
+    source_watchers: Vec<FileWatcher>,
+    budget: MemoryBudget,
+    residency: ResidencyTracker,
+    dependency_index: DependencyIndex,
+    releases: ReleaseQueue,
+    dirty_layers: HashSet<usize>,
+    ids: IdTable,
+    profile: VariantProfile,
 }
 
-impl Database {
-    fn get_images_json(path: &str) -> Result<ImageJSON, Error> {
-        let json_data = fs::read_to_string(path)?;
-        let info: ImageJSON = serde_json::from_str(&json_data)?;
-        Ok(info)
+fn ttf_typeset(localization: &Localization, cfg: &TTFJSONEntry) -> Vec<char> {
+    let mut glyphs: Vec<char> = match (&cfg.glyphs, &cfg.locale) {
+        (Some(g), _) => g.chars().collect(),
+        (None, Some(_)) => runtime_glyphs().collect(),
+        (None, None) => (0_u8 as char..127_u8 as char).collect(),
+    };
+    if let Some(locale) = &cfg.locale {
+        glyphs.extend(localization.glyphs(locale));
+        glyphs.sort();
+        glyphs.dedup();
+    }
+    glyphs
+}
+
+/// Parses one category from the configs of every layer.
+type ConfigLoader<T> = fn(&[Arc<dyn FileSystem>], &[DatabaseJSON], &mut Vec<MigrationRecord>) -> Result<T, Error>;
+
+/// Where the database keeps the entries of one category and how it loads them.
+trait StoredEntry: LoadedEntry + Sized {
+    fn entries(db: &Database) -> &EntryMap<Self>;
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self>;
+    /// Loads in flight on the loader pool, for categories that can be requested.
+    fn pending(_db: &mut Database) -> Option<&mut HashMap<String, LoadHandle<Self::Data>>> {
+        None
+    }
+    /// Loads the data of `entry` from its layer.
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error>;
+}
+
+impl StoredEntry for ImageEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.images
+    }
+
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.images
+    }
+
+    fn pending(db: &mut Database) -> Option<&mut HashMap<String, LoadHandle<Self::Data>>> {
+        Some(&mut db.pending_images)
+    }
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        load_entry_image(&entry.cfg.name, &*db.layers[entry.layer], entry.path())
+    }
+}
+
+impl StoredEntry for AtlasEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.atlases
+    }
+
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.atlases
+    }
+
+    fn pending(db: &mut Database) -> Option<&mut HashMap<String, LoadHandle<Self::Data>>> {
+        Some(&mut db.pending_atlases)
+    }
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        load_entry_atlas(&entry.resolved_cfg(), &*db.layers[entry.layer])
+    }
+}
+
+impl StoredEntry for GeometryEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.geometry
+    }
+
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.geometry
+    }
+
+    fn pending(db: &mut Database) -> Option<&mut HashMap<String, LoadHandle<Self::Data>>> {
+        Some(&mut db.pending_models)
+    }
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        load_entry_model(&entry.resolved_cfg(), &*db.layers[entry.layer])
+    }
+}
+
+impl StoredEntry for TTFEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.ttfs
+    }
+
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.ttfs
+    }
+
+    fn pending(db: &mut Database) -> Option<&mut HashMap<String, LoadHandle<Self::Data>>> {
+        Some(&mut db.pending_ttfs)
+    }
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        let typeset = ttf_typeset(&db.localization, &entry.cfg);
+        load_entry_ttf(&entry.resolved_cfg(), &*db.layers[entry.layer], &typeset)
+    }
+}
+
+impl StoredEntry for ParticleEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.particles
+    }
+
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.particles
+    }
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        load_entry_particle(&entry.cfg, &*db.layers[entry.layer])
+    }
+}
+
+impl StoredEntry for AudioEntry {
+    fn entries(db: &Database) -> &EntryMap<Self> {
+        &db.audio
     }
 
-    fn get_atlases_json(path: &str) -> Result<AtlasJSON, Error> {
-        let json_data = fs::read_to_string(path)?;
-        let info: AtlasJSON = serde_json::from_str(&json_data)?;
-        Ok(info)
+    fn entries_mut(db: &mut Database) -> &mut EntryMap<Self> {
+        &mut db.audio
     }
-    
-    fn get_ttf_json(path: &str) -> Result<TTFJSON, Error> {
-        let json_data = fs::read_to_string(path)?;
-        let info: TTFJSON = serde_json::from_str(&json_data)?;
-        Ok(info)
+
+    fn load_data(db: &Database, entry: &Self) -> Result<Self::Data, Error> {
+        load_entry_audio(&entry.cfg, &db.layers[entry.layer])
+    }
+}
+
+impl Database {
+    /// Reads a config, upgrading it to the current layout. Applied migrations are
+    /// appended to `log`.
+    fn read_config<T: VersionedConfig>(
+        fs: &dyn FileSystem,
+        path: &str,
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<T, Error> {
+        read_versioned(fs, path, log)
     }
-    
+
+    /// The base layer's directory, or the pack file it was opened from.
     pub fn base_path(&self) -> &str {
-        &self.base_path
+        self.layers[0].root()
+    }
+
+    /// Checks every config and the files they reference, collecting all problems
+    /// into one report. Configs are re-read, so duplicates hidden by loading show up.
+    pub fn validate(&self) -> ValidationReport {
+        let layers: Vec<&dyn FileSystem> = self.layers.iter().map(|fs| &**fs).collect();
+        validate_layers(&layers)
+    }
+
+    /// The filesystem of the base layer.
+    pub fn filesystem(&self) -> &Arc<dyn FileSystem> {
+        &self.layers[0]
+    }
+
+    /// Every layer, from the base up to the highest overlay.
+    pub fn layers(&self) -> &[Arc<dyn FileSystem>] {
+        &self.layers
+    }
+
+    /// Index of the layer that supplied the entry `name`.
+    pub fn entry_layer(&self, category: AssetCategory, name: &str) -> Option<usize> {
+        match category {
+            AssetCategory::Image => self.images.get(name).map(|e| e.layer),
+            AssetCategory::Atlas => self.atlases.get(name).map(|e| e.layer),
+            AssetCategory::Geometry => self.geometry.get(name).map(|e| e.layer),
+            AssetCategory::TTF => self.ttfs.get(name).map(|e| e.layer),
+            AssetCategory::Particle => self.particles.get(name).map(|e| e.layer),
+            AssetCategory::Audio => self.audio.get(name).map(|e| e.layer),
+            AssetCategory::Shader => self.shaders.get(name).map(|e| e.layer),
+            AssetCategory::Custom => self.custom.get(name).map(|e| e.layer),
+        }
+    }
+
+    /// Parses the config `cfg` of every layer that declares one and merges the
+    /// entries by name. Entries of higher layers replace those of lower ones.
+    fn load_layered<C: VersionedConfig, E: DatabaseEntry>(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        cfg: fn(&DatabaseJSON) -> &Option<String>,
+        parse: fn(C, usize) -> Vec<E>,
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<E>, Error> {
+        let mut entries = EntryMap::default();
+        for (layer, (fs, info)) in layers.iter().zip(infos).enumerate() {
+            if let Some(path) = cfg(info) {
+                let mut names = HashSet::new();
+                for entry in parse(Database::read_config(&**fs, path, log)?, layer) {
+                    if !names.insert(entry.name().to_string()) {
+                        return Err(duplicate_name_error(&**fs, path, entry.name()));
+                    }
+                    let key = AssetKey::new(E::CATEGORY, entry.name());
+                    if let Err(other) = entries.insert(entry) {
+                        return Err(Error::from(collision_message(&key, &other)));
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn load_images(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<ImageEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.image_cfg, parse_images, log)
+    }
+
+    fn load_atlases(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<AtlasEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.atlas_cfg, parse_atlasses, log)
+    }
+
+    fn load_geometry(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<GeometryEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.geometry_cfg, parse_geometry, log)
+    }
+
+    fn load_ttfs(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<TTFEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.ttf_cfg, parse_ttfs, log)
+    }
+
+    fn load_particles(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<ParticleEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.particle_cfg, parse_particles, log)
+    }
+
+    fn load_audio(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<EntryMap<AudioEntry>, Error> {
+        Database::load_layered(layers, infos, |i| &i.audio_cfg, parse_audio, log)
+    }
+
+    fn load_localization(
+        layers: &[Arc<dyn FileSystem>],
+        infos: &[DatabaseJSON],
+        log: &mut Vec<MigrationRecord>,
+    ) -> Result<Localization, Error> {
+        let mut configs = Vec::new();
+        for (fs, info) in layers.iter().zip(infos) {
+            if let Some(path) = &info.localization_cfg {
+                configs.push(Database::read_config::<LocalizationJSON>(&**fs, path, log)?);
+            }
+        }
+        Ok(Localization::from_configs(configs))
+    }
+
+    /// The particle config of the highest layer that declares one.
+    fn particle_cfg(infos: &[DatabaseJSON]) -> String {
+        infos
+            .iter()
+            .rev()
+            .find_map(|i| i.particle_cfg.clone())
+            .unwrap_or_default()
     }
 
     pub fn new(base_path: &str) -> Result<Self, Error> {
-        let json_data = fs::read_to_string(format!("{}/database.json", base_path))?;
-
-        let info: DatabaseJSON = serde_json::from_str(&json_data)?;
-
-        let images = if let Some(sprite) = info.image_cfg {
-            parse_images(Database::get_images_json(&format!(
-                "{}/{}",
-                base_path,
-                sprite.as_str()
-            ))?)
-        } else {
-            HashMap::new()
+        Database::with_filesystem(Arc::new(DirectoryFs::new(base_path)))
+    }
+
+    /// Opens a database packed into a single file by `build_pack`.
+    pub fn open_pack(path: &str) -> Result<Self, Error> {
+        Database::with_filesystem(Arc::new(Pack::open(path)?))
+    }
+
+    /// Opens a database whose root config sits at the root of `fs`. The root config
+    /// is `database.json`, or `database.ron`, `.toml` or `.yaml`; category configs
+    /// may use any of these formats independently.
+    pub fn with_filesystem(fs: Arc<dyn FileSystem>) -> Result<Self, Error> {
+        Database::with_layers(vec![fs])
+    }
+
+    /// Opens a stack of database directories, base first. See `with_layers`.
+    pub fn with_directories(paths: &[&str]) -> Result<Self, Error> {
+        Database::with_layers(
+            paths
+                .iter()
+                .map(|p| Arc::new(DirectoryFs::new(p)) as Arc<dyn FileSystem>)
+                .collect(),
+        )
+    }
+
+    /// Opens an ordered stack of databases, base first, for DLC and mods. Every layer
+    /// has its own `database.json`; category configs are merged by entry name with
+    /// the higher layer winning, and each entry's files resolve against the layer
+    /// that supplied it.
+    pub fn with_layers(layers: Vec<Arc<dyn FileSystem>>) -> Result<Self, Error> {
+        if layers.is_empty() {
+            return Err(Error::from("a database needs at least one layer"));
+        }
+
+        // Configs are stamped before they are read, so edits made while opening
+        // show up on the first poll.
+        let roots = layers
+            .iter()
+            .map(|fs| database_config(&**fs))
+            .collect::<Result<Vec<_>, String>>()?;
+        let stamps: Vec<_> = layers.iter().zip(&roots).map(|(fs, root)| fs.modified(root)).collect();
+        let mut migrations = Vec::new();
+        let infos = layers
+            .iter()
+            .zip(&roots)
+            .map(|(fs, root)| Database::read_config::<DatabaseJSON>(&**fs, root, &mut migrations))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let config_watchers = layers
+            .iter()
+            .zip(&infos)
+            .zip(roots.iter().zip(stamps))
+            .map(|((fs, info), (root, stamp))| {
+                let mut watcher = FileWatcher::default();
+                for path in Database::config_paths(info) {
+                    watcher.watch(&path, fs.modified(&path));
+                }
+                watcher.watch(root, stamp);
+                watcher
+            })
+            .collect();
+
+        let mut db = Database {
+            images: Database::load_images(&layers, &infos, &mut migrations)?,
+            atlases: Database::load_atlases(&layers, &infos, &mut migrations)?,
+            geometry: Database::load_geometry(&layers, &infos, &mut migrations)?,
+            ttfs: Database::load_ttfs(&layers, &infos, &mut migrations)?,
+            particles: Database::load_particles(&layers, &infos, &mut migrations)?,
+            audio: Database::load_audio(&layers, &infos, &mut migrations)?,
+            particle_cfg: Database::particle_cfg(&infos),
+            localization: Database::load_localization(&layers, &infos, &mut migrations)?,
+            custom: load_custom(&layers, &infos, &mut migrations)?,
+            loaders: HashMap::new(),
+            shaders: discover_shaders(&layers, &infos),
+            migrations,
+            config_watchers,
+            source_watchers: layers.iter().map(|_| FileWatcher::default()).collect(),
+            layers,
+            infos,
+            loader: None,
+            pending_images: HashMap::new(),
+            pending_atlases: HashMap::new(),
+            pending_ttfs: HashMap::new(),
+            pending_models: HashMap::new(),
+            budget: MemoryBudget::unlimited(),
+            residency: ResidencyTracker::default(),
+            dependency_index: DependencyIndex::default(),
+            releases: ReleaseQueue::default(),
+            dirty_layers: HashSet::new(),
+            ids: IdTable::default(),
+            profile: VariantProfile::default(),
         };
 
-        let atlases = if let Some(sprite) = info.atlas_cfg {
-            parse_atlasses(Database::get_atlases_json(&format!(
-                "{}/{}",
-                base_path,
-                sprite.as_str()
-            ))?)
-        } else {
-            HashMap::new()
+        db.rebuild_ids()?;
+        db.select_variants();
+        Ok(db)
+    }
+
+    /// Every config the root config `info` names.
+    fn config_paths(info: &DatabaseJSON) -> Vec<String> {
+        let mut paths = Vec::new();
+        for cfg in [
+            &info.image_cfg,
+            &info.atlas_cfg,
+            &info.geometry_cfg,
+            &info.ttf_cfg,
+            &info.particle_cfg,
+            &info.audio_cfg,
+            &info.localization_cfg,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            paths.push(cfg.clone());
+        }
+        paths.extend(info.categories.values().cloned());
+        paths
+    }
+
+    /// Records the current stamp of every config, so files the database wrote
+    /// itself are not reported as changed.
+    fn watch_configs(&mut self) -> Result<(), Error> {
+        for (layer, fs) in self.layers.iter().enumerate() {
+            let root = database_config(&**fs)?.to_string();
+            for path in Database::config_paths(&self.infos[layer]).into_iter().chain([root]) {
+                let stamp = fs.modified(&path);
+                self.config_watchers[layer].watch(&path, stamp);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a custom category config changed in any layer. The stamps seen are
+    /// added to `seen`, to be recorded once the reload succeeded.
+    fn custom_configs_changed(
+        &self,
+        infos: &[DatabaseJSON],
+        seen: &mut Vec<(usize, String, Option<SystemTime>)>,
+    ) -> bool {
+        let mut changed = false;
+        for (layer, fs) in self.layers.iter().enumerate() {
+            for path in infos[layer].categories.values() {
+                let stamp = fs.modified(path);
+                changed |= self.config_watchers[layer].is_changed(path, stamp);
+                seen.push((layer, path.clone(), stamp));
+            }
+        }
+        changed
+    }
+
+    /// Whether the config `cfg` changed in any layer. The stamps seen are added to
+    /// `seen`, to be recorded once the reload succeeded.
+    /// Parses a category again with `load` if its config `changed`.
+    fn reparse<T>(
+        &mut self,
+        changed: bool,
+        infos: &[DatabaseJSON],
+        load: ConfigLoader<T>,
+    ) -> Result<Option<T>, Error> {
+        if !changed {
+            return Ok(None);
+        }
+        Ok(Some(load(&self.layers, infos, &mut self.migrations)?))
+    }
+
+    fn config_changed(
+        &self,
+        infos: &[DatabaseJSON],
+        cfg: fn(&DatabaseJSON) -> &Option<String>,
+        seen: &mut Vec<(usize, String, Option<SystemTime>)>,
+    ) -> bool {
+        let mut changed = false;
+        for (layer, fs) in self.layers.iter().enumerate() {
+            if let Some(path) = cfg(&infos[layer]) {
+                let stamp = fs.modified(path);
+                changed |= self.config_watchers[layer].is_changed(path, stamp);
+                seen.push((layer, path.clone(), stamp));
+            }
+        }
+        changed
+    }
+
+    /// Checks the root config, the category configs and the source file of every
+    /// loaded entry for modifications. Changed configs are re-parsed: entries whose
+    /// config is unchanged keep their loaded data, everything else is replaced.
+    /// Loaded entries whose source file changed are unloaded so the next fetch
+    /// reloads them. Shader directories are listed again for added and removed
+    /// shaders, and shaders whose sources changed are recompiled on the next fetch.
+    /// Returns every entry that was added, removed, re-parsed or invalidated.
+    ///
+    /// Config stamps are only recorded once every changed config parsed, so a
+    /// failed reload is retried by the next poll.
+    pub fn poll_changes(&mut self) -> Result<Vec<ChangedAsset>, Error> {
+        let mut changed = Vec::new();
+        let mut seen = Vec::new();
+
+        // Everything is parsed and checked before the database changes, so a
+        // broken edit leaves it as it was.
+        let mut infos = self.infos.clone();
+        let mut reload_all = false;
+        for (layer, fs) in self.layers.iter().enumerate() {
+            let root = database_config(&**fs)?;
+            let stamp = fs.modified(root);
+            if self.config_watchers[layer].is_changed(root, stamp) {
+                infos[layer] = Database::read_config(&**fs, root, &mut self.migrations)?;
+                reload_all = true;
+            }
+            seen.push((layer, root.to_string(), stamp));
+        }
+
+        let edited = self.config_changed(&infos, |i| &i.image_cfg, &mut seen);
+        let images = self.reparse(edited || reload_all, &infos, Database::load_images)?;
+        let edited = self.config_changed(&infos, |i| &i.atlas_cfg, &mut seen);
+        let atlases = self.reparse(edited || reload_all, &infos, Database::load_atlases)?;
+        let edited = self.config_changed(&infos, |i| &i.geometry_cfg, &mut seen);
+        let geometry = self.reparse(edited || reload_all, &infos, Database::load_geometry)?;
+        let edited = self.config_changed(&infos, |i| &i.ttf_cfg, &mut seen);
+        let ttfs = self.reparse(edited || reload_all, &infos, Database::load_ttfs)?;
+        let edited = self.config_changed(&infos, |i| &i.particle_cfg, &mut seen);
+        let particles = self.reparse(edited || reload_all, &infos, Database::load_particles)?;
+        let edited = self.config_changed(&infos, |i| &i.audio_cfg, &mut seen);
+        let audio = self.reparse(edited || reload_all, &infos, Database::load_audio)?;
+        let edited = self.config_changed(&infos, |i| &i.localization_cfg, &mut seen);
+        let localization = self.reparse(edited || reload_all, &infos, Database::load_localization)?;
+        let edited = self.custom_configs_changed(&infos, &mut seen);
+        let custom = self.reparse(edited || reload_all, &infos, load_custom)?;
+
+        // Shader directories have no config to watch, so they are listed again.
+        let shaders = discover_shaders(&self.layers, &infos);
+
+        let ids = Database::build_ids(&[
+            (AssetCategory::Image, images.as_ref().unwrap_or(&self.images)),
+            (AssetCategory::Atlas, atlases.as_ref().unwrap_or(&self.atlases)),
+            (AssetCategory::Geometry, geometry.as_ref().unwrap_or(&self.geometry)),
+            (AssetCategory::TTF, ttfs.as_ref().unwrap_or(&self.ttfs)),
+            (AssetCategory::Particle, particles.as_ref().unwrap_or(&self.particles)),
+            (AssetCategory::Audio, audio.as_ref().unwrap_or(&self.audio)),
+            (AssetCategory::Shader, &shaders),
+            (AssetCategory::Custom, custom.as_ref().unwrap_or(&self.custom)),
+        ])?;
+
+        self.infos = infos;
+        if reload_all {
+            self.particle_cfg = Database::particle_cfg(&self.infos);
+        }
+        merge_shaders(&mut self.shaders, shaders, &mut changed);
+
+        if let Some(localization) = localization {
+            self.localization = localization;
+            // Fonts rasterized for a locale may be missing new characters. Fonts
+            // still needed by loaded dependents keep their current glyphs.
+            let stale: Vec<String> = self
+                .ttfs
+                .values()
+                .filter(|e| e.cfg.locale.is_some() && e.is_loaded())
+                .map(|e| e.cfg.name.clone())
+                .collect();
+            for name in stale {
+                if self.unload(AssetCategory::TTF, &name).is_ok() {
+                    changed.push(AssetKey::new(AssetCategory::TTF, &name));
+                }
+            }
+        }
+
+        // Everything parsed: the seen stamps become the baseline. A reloaded root
+        // config may have dropped category configs, which stop being watched.
+        if reload_all {
+            for (layer, watcher) in self.config_watchers.iter_mut().enumerate() {
+                let kept = seen.iter().filter(|(l, ..)| *l == layer).map(|(_, p, _)| p.clone()).collect();
+                watcher.retain(&kept);
+            }
+        }
+        for (layer, path, stamp) in seen {
+            self.config_watchers[layer].watch(&path, stamp);
+        }
+
+        if let Some(fresh) = images {
+            merge_entries(&mut self.images, fresh, AssetCategory::Image, &mut changed);
+        }
+        if let Some(fresh) = atlases {
+            merge_entries(&mut self.atlases, fresh, AssetCategory::Atlas, &mut changed);
+        }
+        if let Some(fresh) = geometry {
+            merge_entries(&mut self.geometry, fresh, AssetCategory::Geometry, &mut changed);
+        }
+        if let Some(fresh) = ttfs {
+            merge_entries(&mut self.ttfs, fresh, AssetCategory::TTF, &mut changed);
+        }
+        if let Some(fresh) = particles {
+            merge_entries(&mut self.particles, fresh, AssetCategory::Particle, &mut changed);
+        }
+        if let Some(fresh) = audio {
+            merge_entries(&mut self.audio, fresh, AssetCategory::Audio, &mut changed);
+        }
+        if let Some(fresh) = custom {
+            merge_entries(&mut self.custom, fresh, AssetCategory::Custom, &mut changed);
+        }
+        self.ids = ids;
+
+        let mut sources = HashSet::new();
+        loaded_sources(&self.images, &mut sources);
+        loaded_sources(&self.atlases, &mut sources);
+        loaded_sources(&self.geometry, &mut sources);
+        loaded_sources(&self.ttfs, &mut sources);
+        loaded_sources(&self.particles, &mut sources);
+        loaded_sources(&self.audio, &mut sources);
+        loaded_sources(&self.custom, &mut sources);
+        shader_sources(&self.shaders, &mut sources);
+        // Sources of loads in flight were stamped when the load started.
+        pending_sources(&self.images, &self.pending_images, &mut sources);
+        pending_sources(&self.atlases, &self.pending_atlases, &mut sources);
+        pending_sources(&self.geometry, &self.pending_models, &mut sources);
+        pending_sources(&self.ttfs, &self.pending_ttfs, &mut sources);
+
+        let mut stale = HashSet::new();
+        for (layer, fs) in self.layers.iter().enumerate() {
+            let paths: HashSet<String> = sources
+                .iter()
+                .filter(|(l, _)| *l == layer)
+                .map(|(_, p)| p.clone())
+                .collect();
+            let watcher = &mut self.source_watchers[layer];
+            watcher.retain(&paths);
+            for path in paths {
+                if watcher.changed(&path, fs.modified(&path)) {
+                    stale.insert((layer, path));
+                }
+            }
+        }
+        if !stale.is_empty() {
+            invalidate_stale(&mut self.images, &stale, AssetCategory::Image, &mut changed);
+            invalidate_stale(&mut self.atlases, &stale, AssetCategory::Atlas, &mut changed);
+            invalidate_stale(&mut self.geometry, &stale, AssetCategory::Geometry, &mut changed);
+            invalidate_stale(&mut self.ttfs, &stale, AssetCategory::TTF, &mut changed);
+            invalidate_stale(&mut self.particles, &stale, AssetCategory::Particle, &mut changed);
+            invalidate_stale(&mut self.audio, &stale, AssetCategory::Audio, &mut changed);
+            invalidate_stale(&mut self.custom, &stale, AssetCategory::Custom, &mut changed);
+            invalidate_stale_shaders(&mut self.shaders, &stale, &mut changed);
+            stale_pending(&self.images, &self.pending_images, &stale, AssetCategory::Image, &mut changed);
+            stale_pending(&self.atlases, &self.pending_atlases, &stale, AssetCategory::Atlas, &mut changed);
+            stale_pending(&self.geometry, &self.pending_models, &stale, AssetCategory::Geometry, &mut changed);
+            stale_pending(&self.ttfs, &self.pending_ttfs, &stale, AssetCategory::TTF, &mut changed);
+        }
+
+        for asset in &changed {
+            self.forget_entry(asset);
+        }
+        if !changed.is_empty() {
+            // Only re-parsed entries, already in `changed`, need a variant.
+            self.select_variants();
+        }
+
+        Ok(changed)
+    }
+
+    /// Drops the residency and any in-flight load of an entry whose config or data
+    /// went stale. In-flight loads would otherwise resurrect the stale data.
+    fn forget_entry(&mut self, key: &AssetKey) {
+        self.residency.forget(key);
+        self.dependency_index.forget(key);
+        match key.category {
+            AssetCategory::Image => self.pending_images.remove(&key.name).map(|_| ()),
+            AssetCategory::Atlas => self.pending_atlases.remove(&key.name).map(|_| ()),
+            AssetCategory::Geometry => self.pending_models.remove(&key.name).map(|_| ()),
+            AssetCategory::TTF => self.pending_ttfs.remove(&key.name).map(|_| ()),
+            AssetCategory::Particle | AssetCategory::Audio | AssetCategory::Shader | AssetCategory::Custom => None,
         };
+    }
+
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    /// Replaces the memory budget and immediately unloads entries until it is met.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.budget = budget;
+        self.enforce_budget(None);
+    }
+
+    /// Bytes held by every loaded entry.
+    pub fn resident_bytes(&self) -> usize {
+        self.residency.total_bytes()
+    }
+
+    pub fn category_bytes(&self, category: AssetCategory) -> usize {
+        self.residency.category_bytes(category)
+    }
+
+    /// Keeps an entry resident after its last handle drops and exempts it from
+    /// budget eviction. The entry doesn't need to be loaded.
+    pub fn pin(&mut self, category: AssetCategory, name: &str) {
+        self.residency.pin(&AssetKey::new(category, name));
+    }
+
+    pub fn unpin(&mut self, category: AssetCategory, name: &str) {
+        self.residency.unpin(&AssetKey::new(category, name));
+        self.enforce_budget(None);
+    }
+
+    /// Drops the loaded data of an entry. The next fetch loads it again. Refused
+    /// while loaded entries still depend on it.
+    pub fn unload(&mut self, category: AssetCategory, name: &str) -> Result<(), Error> {
+        let dependents = self.loaded_dependents(category, name);
+        if !dependents.is_empty() {
+            let names: Vec<&str> = dependents.iter().map(|k| k.name.as_str()).collect();
+            return Err(Error::from(format!(
+                "cannot unload {}: still needed by {}",
+                name,
+                names.join(", ")
+            )));
+        }
 
-        let ttfs = if let Some(ttf) = info.ttf_cfg {
-            parse_ttfs(Database::get_ttf_json(&format!(
-                "{}/{}",
-                base_path,
-                ttf.as_str()
-            ))?)
-        } else {
-            HashMap::new()
+        let found = match category {
+            AssetCategory::Image => self.images.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Atlas => self.atlases.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Geometry => self.geometry.get_mut(name).map(|e| e.unload()),
+            AssetCategory::TTF => self.ttfs.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Particle => self.particles.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Audio => self.audio.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Shader => self.shaders.get_mut(name).map(|e| e.unload()),
+            AssetCategory::Custom => self.custom.get_mut(name).map(|e| e.unload()),
         };
 
-        Ok(Database {
-            base_path: base_path.to_string(),
-            images,
-            atlases,
-            ttfs,
-            particle_cfg: if info.particle_cfg.is_some() {
-                info.particle_cfg.unwrap().clone()
-            } else {
-                "".to_string()
-            },
-        })
+        match found {
+            Some(()) => {
+                let key = AssetKey::new(category, name);
+                self.residency.forget(&key);
+                self.dependency_index.forget(&key);
+                Ok(())
+            }
+            None => Err(Error::LookupError(LookupError {
+                entry: name.to_string(),
+            })),
+        }
     }
 
-    pub fn particle_system_cfg_path(&self) -> Result<String, Error> {
-        return Ok(self.particle_cfg.clone());
-    }
-
-//    pub fn fetch_sprite(&mut self, name: &str) -> Result<&ImageEntry, Error> {
-//        // TODO probably async this.
-//        if let Some(entry) = self.sprites.get_mut(name) {
-//            if entry.loaded.is_none() {
-//                entry.load(&self.base_path);
-//            }
-//
-//            return Ok(entry);
-//        }
-//
-//        return Err(Error::LookupError(LookupError {
-//            entry: name.to_string(),
-//        }));
-//    }
-
-    pub fn fetch_ttf(&mut self, name: &str) -> Result<&TTFEntry, Error> {
-        let default_typeset: Vec<char> = (0 as u8 as char..127 as u8 as char).collect();
-        // TODO probably async this.
-        if let Some(entry) = self.ttfs.get_mut(name) {
-            if entry.loaded.is_none() {
-                #[allow(unused_assignments)]
-                let mut str = Vec::new();
-                let glyphs: &[char] = match entry.cfg.glyphs.clone() {
-                    Some(g) => {
-                        str = g.chars().collect();
-                        &str
+    fn note_fetch(&mut self, key: &AssetKey, bytes: usize) {
+        self.mark_resident(key, bytes);
+        self.enforce_budget(Some(key));
+    }
+
+    /// Records that `key` is loaded and occupies `bytes`.
+    fn mark_resident(&mut self, key: &AssetKey, bytes: usize) {
+        self.residency.touch(key, bytes);
+        let deps = self.dependencies(key.category, &key.name);
+        self.dependency_index.record(key, deps);
+    }
+
+    /// Unloads the least recently fetched entries until the budget is met. Entries
+    /// that are in use or that resident entries depend on are kept; the ones freed
+    /// by an eviction are considered again in the next round.
+    fn enforce_budget(&mut self, current: Option<&AssetKey>) {
+        loop {
+            let mut keep: HashSet<AssetKey> = current.into_iter().cloned().collect();
+            collect_in_use(&self.images, AssetCategory::Image, &mut keep);
+            collect_in_use(&self.atlases, AssetCategory::Atlas, &mut keep);
+            collect_in_use(&self.geometry, AssetCategory::Geometry, &mut keep);
+            collect_in_use(&self.ttfs, AssetCategory::TTF, &mut keep);
+            collect_in_use(&self.particles, AssetCategory::Particle, &mut keep);
+            collect_in_use(&self.audio, AssetCategory::Audio, &mut keep);
+            collect_in_use(&self.custom, AssetCategory::Custom, &mut keep);
+            for shader in self.shaders.values().filter(|s| s.handle_count() > 0) {
+                keep.insert(AssetKey::new(AssetCategory::Shader, &shader.name));
+            }
+            keep.extend(self.dependency_index.needed().cloned());
+
+            let mut evicted = false;
+            for key in self.residency.select_evictions(&self.budget, &keep) {
+                match self.unload(key.category, &key.name) {
+                    Ok(()) => evicted = true,
+                    // The entry is gone; its residency only blocks the budget.
+                    Err(Error::LookupError(_)) => {
+                        self.forget_entry(&key);
+                        evicted = true;
                     }
-                    None => &default_typeset,
-                };
-                entry.load(&self.base_path, glyphs);
+                    // Refused while an entry depends on it. It stays resident and
+                    // is retried once that entry is evicted.
+                    Err(_) => {}
+                }
+            }
+
+            if !evicted {
+                break;
+            }
+        }
+    }
+
+    /// Unloads entries whose last asset handle has been dropped. Pinned entries and
+    /// entries that loaded entries depend on stay resident. With
+    /// `MemoryBudget::keep_released` they stay resident until the budget needs
+    /// their memory instead.
+    fn release_dropped(&mut self) {
+        let mut released = Vec::new();
+        for (key, addr) in self.releases.drain() {
+            let is_last = match key.category {
+                AssetCategory::Image => self.images.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::Atlas => self.atlases.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::Geometry => self.geometry.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::TTF => self.ttfs.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::Particle => self.particles.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::Audio => self.audio.get(&key.name).map(|e| is_released(e, addr)),
+                AssetCategory::Shader => self.shaders.get(&key.name).map(|e| e.is_released(addr)),
+                AssetCategory::Custom => self.custom.get(&key.name).map(|e| is_released(e, addr)),
+            }
+            .unwrap_or(false);
+
+            if is_last {
+                released.push(key);
+            }
+        }
+
+        if released.is_empty() {
+            return;
+        }
+
+        if self.budget.keep_released {
+            self.enforce_budget(None);
+            return;
+        }
+
+        for key in released {
+            if !self.residency.is_pinned(&key) {
+                // Refused while a loaded entry depends on it; it is released along
+                // with that entry.
+                let _ = self.unload(key.category, &key.name);
             }
+        }
+    }
 
-            return Ok(entry);
+    fn make_asset<T>(&self, key: AssetKey, data: &Option<Arc<T>>) -> Result<Asset<T>, Error> {
+        match data {
+            Some(data) => Ok(Asset::new(key, data.clone(), self.releases.clone())),
+            None => Err(Error::LookupError(LookupError { entry: key.name })),
         }
+    }
 
-        return Err(Error::LookupError(LookupError {
-            entry: name.to_string(),
-        }));
+    pub fn particle_system_cfg_path(&self) -> Result<String, Error> {
+        Ok(self.particle_cfg.clone())
     }
 
-//    pub fn fetch_sprite_sheet(&mut self, name: &str) -> Result<&AtlasEntry, Error> {
-//        // TODO probably async this.
-//        if let Some(entry) = self.sprite_sheets.get_mut(name) {
-//            if entry.loaded.is_none() {
-//                entry.load(&self.base_path);
-//            }
-//
-//            return Ok(entry);
-//        }
-//
-//        return Err(Error::LookupError(LookupError {
-//            entry: name.to_string(),
-//        }));
-//    }
-}
+    /// Loads the render graph of the highest layer that declares one. Graphs that
+    /// fail validation are rejected with every issue in the error.
+    pub fn fetch_render_graph(&self) -> Result<RenderGraph, Error> {
+        let found = self
+            .layers
+            .iter()
+            .zip(&self.infos)
+            .rev()
+            .find_map(|(fs, info)| info.render_graph_path.as_ref().map(|path| (fs, path)));
 
-#[test]
-fn test_database() {
-    let res = Database::new("/wksp/database");
-    assert!(res.is_ok());
+        let (fs, path) = match found {
+            Some(found) => found,
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: "render_graph_path".to_string(),
+                }))
+            }
+        };
 
-    let mut db = res.unwrap();
-//    let sprite = db.fetch_sprite("name");
-//    assert!(sprite.is_ok());
-//
-//    let sprite = db.fetch_sprite_sheet("name");
-//    assert!(sprite.is_ok());
+        let graph: RenderGraph = Database::read_config(&**fs, path, &mut Vec::new())?;
+        let report = graph.validate(path);
+        if !report.is_ok() {
+            return Err(Error::from(report.to_string().trim_end().to_string()));
+        }
+        Ok(graph)
+    }
+
+    /// Names of every shader found below the `shader_path` of any layer.
+    pub fn shader_names(&self) -> impl Iterator<Item = &str> {
+        self.shaders.keys().map(|k| k.as_str())
+    }
+
+    /// Fetches the shader `name` with every stage compiled to SPIR-V. Shaders are
+    /// compiled on first fetch and count against the memory budget like any other
+    /// entry; `poll_changes` drops them when a source changes.
+    pub fn fetch_shader(&mut self, name: &str) -> Result<Asset<Shader>, Error> {
+        self.release_dropped();
+
+        let entry = match self.shaders.get_mut(name) {
+            Some(entry) => entry,
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+        if !entry.is_loaded() {
+            for source in &entry.sources {
+                watch_source(&mut self.source_watchers, &self.layers, entry.layer, &source.path);
+            }
+            entry.load(&*self.layers[entry.layer])?;
+        }
+
+        let bytes = entry.byte_size();
+        let key = AssetKey::new(AssetCategory::Shader, name);
+        let asset = self.make_asset(key.clone(), &self.shaders[name].loaded)?;
+        self.note_fetch(&key, bytes);
+        Ok(asset)
+    }
+
+    pub fn fetch_image(&mut self, name: &str) -> Result<Asset<ImageLoadInfo<u8>>, Error> {
+        self.fetch_entry::<ImageEntry>(name)
+    }
+
+    pub fn fetch_atlas(&mut self, name: &str) -> Result<Asset<Atlas>, Error> {
+        self.fetch_entry::<AtlasEntry>(name)
+    }
+
+    /// Fetches the entry `name` of `E`'s category, loading it if needed. A load
+    /// already in flight is waited for rather than started again.
+    fn fetch_entry<E: StoredEntry>(&mut self, name: &str) -> Result<Asset<E::Data>, Error> {
+        self.release_dropped();
+
+        if let Some(handle) = E::pending(self).and_then(|pending| pending.remove(name)) {
+            handle.wait();
+            if let Some(Ok(data)) = handle.take() {
+                self.store_loaded::<E>(name, data, handle.decode_time().unwrap_or_default());
+            }
+        }
+
+        let source = match E::entries(self).get(name) {
+            Some(entry) if entry.is_loaded() => None,
+            Some(entry) => Some((entry.layer(), entry.path().to_string())),
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+        if let Some((layer, path)) = source {
+            watch_source(&mut self.source_watchers, &self.layers, layer, &path);
+            let (data, time) = timed(|| E::load_data(self, &E::entries(self)[name]))?;
+            self.store_loaded::<E>(name, data, time);
+        }
+
+        let key = AssetKey::new(E::CATEGORY, name);
+        self.note_fetch(&key, E::entries(self)[name].byte_size());
+        self.make_asset(key, E::entries(self)[name].loaded())
+    }
+
+    /// Moves data loaded in `time` into the entry `name`. Returns false when the
+    /// entry no longer exists.
+    fn store_loaded<E: StoredEntry>(&mut self, name: &str, data: E::Data, time: Duration) -> bool {
+        let fs = match E::entries(self).get(name) {
+            Some(entry) => self.layers[entry.layer()].clone(),
+            None => return false,
+        };
+        if let Some(entry) = E::entries_mut(self).get_mut(name) {
+            entry.set_loaded(data, time, &*fs);
+        }
+        true
+    }
+
+    fn loader(&mut self) -> &LoaderPool {
+        self.loader.get_or_insert_with(LoaderPool::default)
+    }
+
+    /// Starts loading the image `name` on the loader pool. The returned handle can be
+    /// polled each frame; the result is moved into the database by `poll_loads`.
+    pub fn request_image(&mut self, name: &str) -> Result<LoadHandle<ImageLoadInfo<u8>>, Error> {
+        if let Some(handle) = self.pending_images.get(name) {
+            return Ok(handle.clone());
+        }
+
+        let (layer, path, fs) = match self.images.get(name) {
+            Some(entry) if entry.loaded.is_some() => return Ok(LoadHandle::ready(name)),
+            Some(entry) => (entry.layer, entry.path().to_string(), self.layers[entry.layer].clone()),
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+
+        watch_source(&mut self.source_watchers, &self.layers, layer, &path);
+        let entry = name.to_string();
+        let handle = self
+            .loader()
+            .submit(name, move || load_entry_image(&entry, &*fs, &path));
+        self.pending_images.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Starts loading the atlas `name` on the loader pool.
+    pub fn request_atlas(&mut self, name: &str) -> Result<LoadHandle<Atlas>, Error> {
+        if let Some(handle) = self.pending_atlases.get(name) {
+            return Ok(handle.clone());
+        }
+
+        let (layer, path, cfg, fs) = match self.atlases.get(name) {
+            Some(entry) if entry.loaded.is_some() => return Ok(LoadHandle::ready(name)),
+            Some(entry) => (
+                entry.layer,
+                entry.path().to_string(),
+                entry.resolved_cfg(),
+                self.layers[entry.layer].clone(),
+            ),
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+
+        watch_source(&mut self.source_watchers, &self.layers, layer, &path);
+        let handle = self
+            .loader()
+            .submit(name, move || load_entry_atlas(&cfg, &*fs));
+        self.pending_atlases.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Starts rasterizing the font `name` on the loader pool.
+    pub fn request_ttf(&mut self, name: &str) -> Result<LoadHandle<TTFont>, Error> {
+        if let Some(handle) = self.pending_ttfs.get(name) {
+            return Ok(handle.clone());
+        }
+
+        let (layer, path, cfg, typeset, fs) = match self.ttfs.get(name) {
+            Some(entry) if entry.loaded.is_some() => return Ok(LoadHandle::ready(name)),
+            Some(entry) => (
+                entry.layer,
+                entry.path().to_string(),
+                entry.resolved_cfg(),
+                ttf_typeset(&self.localization, &entry.cfg),
+                self.layers[entry.layer].clone(),
+            ),
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+
+        watch_source(&mut self.source_watchers, &self.layers, layer, &path);
+        let handle = self
+            .loader()
+            .submit(name, move || load_entry_ttf(&cfg, &*fs, &typeset));
+        self.pending_ttfs.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Starts loading the model `name` on the loader pool.
+    pub fn request_model(&mut self, name: &str) -> Result<LoadHandle<Model>, Error> {
+        if let Some(handle) = self.pending_models.get(name) {
+            return Ok(handle.clone());
+        }
+
+        let (layer, path, cfg, fs) = match self.geometry.get(name) {
+            Some(entry) if entry.loaded.is_some() => return Ok(LoadHandle::ready(name)),
+            Some(entry) => (
+                entry.layer,
+                entry.path().to_string(),
+                entry.resolved_cfg(),
+                self.layers[entry.layer].clone(),
+            ),
+            None => {
+                return Err(Error::LookupError(LookupError {
+                    entry: name.to_string(),
+                }))
+            }
+        };
+
+        watch_source(&mut self.source_watchers, &self.layers, layer, &path);
+        let handle = self
+            .loader()
+            .submit(name, move || load_entry_model(&cfg, &*fs));
+        self.pending_models.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Moves every finished background load into its entry. Returns the entries that
+    /// became resident and those whose load failed, each reported once; the handles
+    /// of failed loads keep reporting the failure.
+    pub fn poll_loads(&mut self) -> PolledLoads {
+        self.release_dropped();
+        let mut polled = PolledLoads::default();
+
+        self.poll_pending::<ImageEntry>(&mut polled);
+        self.poll_pending::<AtlasEntry>(&mut polled);
+        self.poll_pending::<TTFEntry>(&mut polled);
+        self.poll_pending::<GeometryEntry>(&mut polled);
+        self.enforce_budget(None);
+
+        polled
+    }
+
+    /// Moves the finished background loads of `E`'s category into their entries.
+    fn poll_pending<E: StoredEntry>(&mut self, polled: &mut PolledLoads) {
+        let pending = match E::pending(self) {
+            Some(pending) => std::mem::take(pending),
+            None => return,
+        };
+
+        let mut still_pending = HashMap::new();
+        for (name, handle) in pending {
+            let key = AssetKey::new(E::CATEGORY, &name);
+            match handle.take() {
+                Some(Ok(data)) => {
+                    let time = handle.decode_time().unwrap_or_default();
+                    if self.store_loaded::<E>(&name, data, time) {
+                        self.mark_resident(&key, E::entries(self)[name.as_str()].byte_size());
+                        polled.ready.push(key);
+                    }
+                }
+                Some(Err(err)) => polled.failed.push((key, err)),
+                None if handle.is_pending() => {
+                    still_pending.insert(name, handle);
+                }
+                None => {}
+            }
+        }
+        if let Some(pending) = E::pending(self) {
+            *pending = still_pending;
+        }
+    }
+
+    /// Fetches the model `name`, loading it through its geometry entry so the
+    /// configured mesh overrides are applied.
+    pub fn fetch_model(&mut self, name: &str) -> Result<Asset<Model>, Error> {
+        self.fetch_entry::<GeometryEntry>(name)
+    }
+
+    pub fn fetch_ttf(&mut self, name: &str) -> Result<Asset<TTFont>, Error> {
+        self.fetch_entry::<TTFEntry>(name)
+    }
+
+    /// Fetches the particle system `name` along with its sheet image.
+    pub fn fetch_particle_system(&mut self, name: &str) -> Result<Asset<ParticleSystem>, Error> {
+        self.fetch_entry::<ParticleEntry>(name)
+    }
+
+    /// Fetches the audio clip `name`. Preloaded entries are decoded on first
+    /// fetch; streaming entries are decoded by the clip's `stream`.
+    pub fn fetch_audio(&mut self, name: &str) -> Result<Asset<AudioClip>, Error> {
+        self.fetch_entry::<AudioEntry>(name)
+    }
+}
+
+#[test]
+fn test_database() {
+    let res = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database"));
+    assert!(res.is_ok());
+
+    let mut db = res.unwrap();
+    assert!(db.fetch_ttf("font").is_ok());
+    assert!(db.fetch_model("model").is_ok());
+}
+
+#[test]
+fn test_request_ttf() {
+    let mut db = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database")).unwrap();
+
+    let handle = db.request_ttf("font").unwrap();
+    assert_eq!(handle.wait(), LoadStatus::Ready);
+    let polled = db.poll_loads();
+    assert_eq!(polled.ready, vec![AssetKey::new(AssetCategory::TTF, "font")]);
+    assert!(polled.failed.is_empty());
+    assert!(db.request_ttf("font").unwrap().is_ready());
+    assert!(db.fetch_ttf("font").unwrap().atlas.is_some());
+
+    assert!(db.request_ttf("missing").is_err());
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    fs.insert("images.json", r#"{ "images": [ { "name": "ghost", "path": "ghost.png" } ] }"#);
+    let mut db = Database::with_filesystem(fs).unwrap();
+    assert!(matches!(db.request_image("ghost").unwrap().wait(), LoadStatus::Failed(_)));
+    let polled = db.poll_loads();
+    assert!(polled.ready.is_empty());
+    assert_eq!(polled.failed.len(), 1);
+    assert_eq!(polled.failed[0].0, AssetKey::new(AssetCategory::Image, "ghost"));
+    assert!(db.poll_loads().failed.is_empty());
+}
+
+#[cfg(test)]
+use std::fs;
+
+/// A scratch directory unique to one test run, removed when dropped.
+#[cfg(test)]
+struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("remouillage_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A blank `width` x `height` RGBA image encoded as PNG.
+#[cfg(test)]
+fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::RgbaImage::new(width, height)
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_missing_image_is_error() {
+    let dir = TestDir::new("missing_image");
+    fs::write(dir.join("database.json"), r#"{ "image_cfg": "images.json" }"#).unwrap();
+    fs::write(
+        dir.join("images.json"),
+        r#"{ "images": [ { "name": "ghost", "path": "ghost.png" } ] }"#,
+    )
+    .unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap()).unwrap();
+    match db.fetch_image("ghost") {
+        Err(Error::LoadingError(err)) => {
+            assert_eq!(err.entry, "ghost");
+            assert!(err.path.ends_with("/ghost.png"));
+            assert!(!err.cause.is_empty());
+        }
+        _ => panic!("expected a loading error"),
+    }
+}
+
+#[test]
+fn test_fetch_model() {
+    let mut db = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database")).unwrap();
+
+    let model = db.fetch_model("model").unwrap();
+    assert_eq!(model.meshes[0].name, "body");
+    assert_eq!(model.meshes[0].render_mask.as_deref(), Some("shadow"));
+    assert!(model.meshes[1..]
+        .iter()
+        .all(|m| m.render_mask.as_deref() == Some("standard")));
+
+    // Overrides follow the mesh name in the file, not the position.
+    let sample = DirectoryFs::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database"));
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "geometry_cfg": "geometry.json" }"#);
+    fs.insert(
+        "geometry.json",
+        r#"{ "models": [ { "name": "model", "path": "model.gltf", "render_mask": "standard",
+            "meshes": [ { "mesh": "Cube.003", "material": "cloth" } ] } ] }"#,
+    );
+    fs.insert("model.gltf", sample.read("model.gltf").unwrap());
+    let mut db = Database::with_filesystem(fs).unwrap();
+    let model = db.fetch_model("model").unwrap();
+    assert_eq!(model.meshes[2].material.name, "cloth");
+    assert!(model.meshes.iter().filter(|m| m.material.name == "cloth").count() == 1);
+
+    let empty = br#"{ "asset": { "version": "2.0" }, "meshes": [ { "name": "empty", "primitives": [] } ] }"#;
+    assert!(load_gltf_model_from_slice(empty, &|_| None).is_err());
+
+    // A mesh of two triangle primitives, and one whose normals are short a vertex.
+    let mut buffer = Vec::new();
+    let floats = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+    let uvs = [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0];
+    buffer.extend(floats.iter().chain(&uvs).flat_map(|f| f.to_le_bytes()));
+    buffer.extend([0u16, 1, 2, 0].iter().flat_map(|i| i.to_le_bytes()));
+    let gltf = r#"{ "asset": { "version": "2.0" },
+        "buffers": [ { "uri": "quad.bin", "byteLength": 104 } ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 72, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 96, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" },
+            { "bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR" },
+            { "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3" }
+        ],
+        "meshes": [ { "name": "quad", "primitives": [
+            { "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }, "indices": 3 },
+            { "attributes": { "POSITION": 0, "NORMAL": NORMALS, "TEXCOORD_0": 2 }, "indices": 3 }
+        ] } ] }"#;
+    let read_uri = |uri: &str| Some(buffer.clone()).filter(|_| uri == "quad.bin");
+
+    let model = load_gltf_model_from_slice(gltf.replace("NORMALS", "1").as_bytes(), &read_uri).unwrap();
+    assert_eq!(model.meshes[0].vertices.len(), 6);
+    assert_eq!(model.meshes[0].indices, [0, 1, 2, 3, 4, 5]);
+    assert!(load_gltf_model_from_slice(gltf.replace("NORMALS", "4").as_bytes(), &read_uri).is_err());
+
+    // The same buffer embedded as a base64 data URI.
+    use base64::Engine;
+    let uri = format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&buffer)
+    );
+    let embedded = gltf.replace("NORMALS", "1").replace("quad.bin", &uri);
+    let model = load_gltf_model_from_slice(embedded.as_bytes(), &|_| None).unwrap();
+    assert_eq!(model.meshes[0].indices, [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_poll_changes() {
+    let dir = TestDir::new("poll_changes");
+    fs::write(dir.join("database.json"), r#"{ "image_cfg": "images.json" }"#).unwrap();
+    fs::write(
+        dir.join("images.json"),
+        r#"{ "images": [ { "name": "a", "path": "a.png" } ] }"#,
+    )
+    .unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap()).unwrap();
+    assert!(db.poll_changes().unwrap().is_empty());
+
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    let touch = |contents: &str| {
+        fs::write(dir.join("images.json"), contents).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(dir.join("images.json"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+    };
+
+    // A broken edit keeps failing until it is fixed, and the fix is still seen.
+    touch(r#"{ "images": [ { "name": "a", "path": "a.png" }, "#);
+    assert!(db.poll_changes().is_err());
+    assert!(db.poll_changes().is_err());
+
+    touch(r#"{ "images": [ { "name": "a", "path": "a.png" }, { "name": "b", "path": "b.png" } ] }"#);
+    let changed = db.poll_changes().unwrap();
+    assert_eq!(
+        changed,
+        vec![ChangedAsset {
+            category: AssetCategory::Image,
+            name: "b".to_string(),
+        }]
+    );
+    assert!(db.fetch_image("b").is_err());
+    assert!(db.poll_changes().unwrap().is_empty());
+}
+
+#[test]
+fn test_poll_changes_sees_edits_before_first_poll() {
+    let png = png_bytes(2, 2);
+
+    let mem = Arc::new(MemoryFs::new());
+    mem.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    mem.insert("images.json", r#"{ "images": [ { "name": "a", "path": "a.png" } ] }"#);
+    mem.insert("a.png", png.clone());
+
+    let mut db = Database::with_filesystem(mem.clone()).unwrap();
+    drop(db.fetch_image("a").unwrap());
+
+    // Edited after the load but before any poll.
+    mem.insert("a.png", png);
+    let changed = db.poll_changes().unwrap();
+    assert_eq!(changed, vec![AssetKey::new(AssetCategory::Image, "a")]);
+}
+
+#[test]
+fn test_memory_budget() {
+    let dir = TestDir::new("memory_budget");
+    fs::write(dir.join("database.json"), r#"{ "image_cfg": "images.json" }"#).unwrap();
+    fs::write(
+        dir.join("images.json"),
+        r#"{ "images": [
+            { "name": "a", "path": "a.png" },
+            { "name": "b", "path": "b.png" },
+            { "name": "c", "path": "c.png" }
+        ] }"#,
+    )
+    .unwrap();
+    for name in ["a", "b", "c"] {
+        fs::write(dir.join(format!("{}.png", name)), png_bytes(4, 4)).unwrap();
+    }
+
+    // Each 4x4 RGBA8 image is 64 bytes, so only two fit.
+    let mut db = Database::new(dir.to_str().unwrap()).unwrap();
+    db.set_memory_budget(
+        MemoryBudget::unlimited()
+            .with_category(AssetCategory::Image, 128)
+            .keep_released(),
+    );
+
+    db.fetch_image("a").unwrap();
+    db.fetch_image("b").unwrap();
+    db.fetch_image("c").unwrap();
+    assert_eq!(db.resident_bytes(), 128);
+    assert!(db.images["a"].loaded.is_none());
+
+    db.pin(AssetCategory::Image, "b");
+    db.fetch_image("a").unwrap();
+    assert!(db.images["b"].loaded.is_some());
+    assert!(db.images["c"].loaded.is_none());
+}
+
+#[test]
+fn test_asset_handles() {
+    let dir = TestDir::new("asset_handles");
+    fs::write(dir.join("database.json"), r#"{ "image_cfg": "images.json" }"#).unwrap();
+    fs::write(
+        dir.join("images.json"),
+        r#"{ "images": [ { "name": "a", "path": "a.png" } ] }"#,
+    )
+    .unwrap();
+    fs::write(dir.join("a.png"), png_bytes(4, 4)).unwrap();
+
+    let mut db = Database::new(dir.to_str().unwrap()).unwrap();
+    let first = db.fetch_image("a").unwrap();
+    let second = db.fetch_image("a").unwrap();
+    assert!(first.ptr_eq(&second));
+    assert_eq!(first.size, [4, 4]);
+
+    // Dropping one of several handles keeps the entry loaded.
+    drop(db.fetch_image("a").unwrap());
+    db.poll_loads();
+    assert!(db.images["a"].loaded.is_some());
+
+    // Handles in use are never evicted, even over budget.
+    db.set_memory_budget(MemoryBudget::unlimited().with_category(AssetCategory::Image, 0));
+    let weak = first.downgrade();
+    drop(first);
+    db.poll_loads();
+    assert!(db.images["a"].loaded.is_some());
+
+    // The last drop, from any clone or thread, unloads the entry.
+    db.set_memory_budget(MemoryBudget::unlimited());
+    let clones: Vec<_> = (0..8).map(|_| second.clone()).collect();
+    drop(second);
+    std::thread::scope(|s| {
+        for clone in clones {
+            s.spawn(move || drop(clone));
+        }
+    });
+    db.poll_loads();
+    assert!(db.images["a"].loaded.is_none());
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn test_open_pack() {
+    let dir = TestDir::new("open_pack");
+    let out = dir.join("sample.pack");
+    let out = out.to_str().unwrap();
+    build_pack(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database"),
+        out,
+        Compression::Lz4,
+    )
+    .unwrap();
+
+    let mut db = Database::open_pack(out).unwrap();
+    assert_eq!(db.base_path(), out);
+    assert!(db.fetch_ttf("font").unwrap().atlas.is_some());
+    assert!(!db.fetch_model("model").unwrap().meshes.is_empty());
+    assert!(db.poll_changes().unwrap().is_empty());
+
+    // Zip archives resolve inside the pack as they do in the directory.
+    let pack = Pack::open(out).unwrap();
+    let dir_fs = DirectoryFs::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database"));
+    let zipped = "fonts/Roboto.zip/Roboto-Regular.ttf";
+    assert!(FileSystem::exists(&pack, zipped));
+    assert_eq!(FileSystem::read(&pack, zipped).unwrap(), dir_fs.read(zipped).unwrap());
+    assert_eq!(pack.file_size(zipped), dir_fs.file_size(zipped));
+    assert_eq!(pack.list("fonts/Roboto.zip"), dir_fs.list("fonts/Roboto.zip"));
+    assert!(pack.list("fonts/Roboto.zip").iter().any(|f| f == zipped));
+
+    // Sizes in a corrupt index are rejected before anything is allocated for them.
+    let header = |count: u32| [b"RMPK".as_slice(), &1u32.to_le_bytes(), &count.to_le_bytes()].concat();
+    let corrupt = dir.join("corrupt.pack");
+    fs::write(&corrupt, header(u32::MAX)).unwrap();
+    assert!(Pack::open(corrupt.to_str().unwrap()).is_err());
+
+    let mut huge_name = header(1);
+    huge_name.extend_from_slice(&u32::MAX.to_le_bytes());
+    huge_name.extend_from_slice(&[0; 29]);
+    fs::write(&corrupt, huge_name).unwrap();
+    assert!(Pack::open(corrupt.to_str().unwrap()).is_err());
+
+    let mut huge_blob = header(1);
+    huge_blob.extend_from_slice(&1u32.to_le_bytes());
+    huge_blob.push(b'a');
+    for value in [0, u64::MAX, u64::MAX] {
+        huge_blob.extend_from_slice(&value.to_le_bytes());
+    }
+    huge_blob.push(0);
+    fs::write(&corrupt, huge_blob).unwrap();
+    assert!(Pack::open(corrupt.to_str().unwrap()).is_err());
+
+    // Raw lengths beyond what the compressed blob can hold are rejected too.
+    let mut compressed = PackBuilder::new();
+    compressed.add("a.txt", vec![b'a'; 64], Compression::Lz4);
+    compressed.add("b.txt", vec![b'b'; 64], Compression::Zstd);
+    compressed.write(corrupt.to_str().unwrap()).unwrap();
+    let valid = fs::read(&corrupt).unwrap();
+    let pack = Pack::open(corrupt.to_str().unwrap()).unwrap();
+    assert_eq!(pack.read("b.txt").unwrap(), vec![b'b'; 64]);
+    let raw_length_at = |name: &str| {
+        let entry = pack.entry(name).unwrap();
+        let start = valid.windows(name.len()).position(|w| w == name.as_bytes()).unwrap() + name.len();
+        assert_eq!(valid[start + 16..start + 24], entry.raw_length.to_le_bytes());
+        start + 16
+    };
+    let (lz4_at, zstd_at) = (raw_length_at("a.txt"), raw_length_at("b.txt"));
+    drop(pack);
+
+    let mut huge_lz4 = valid.clone();
+    huge_lz4[lz4_at..lz4_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    fs::write(&corrupt, huge_lz4).unwrap();
+    assert!(Pack::open(corrupt.to_str().unwrap()).is_err());
+
+    let mut huge_zstd = valid;
+    huge_zstd[zstd_at..zstd_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    fs::write(&corrupt, huge_zstd).unwrap();
+    assert!(Pack::open(corrupt.to_str().unwrap()).unwrap().read("b.txt").is_err());
+}
+
+#[test]
+fn test_filesystems() {
+    let sample = concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database");
+    let dir = DirectoryFs::new(sample);
+    assert!(dir.exists("fonts/Roboto.zip/Roboto-Regular.ttf"));
+    assert!(!dir.exists("fonts/Roboto.zip/Missing.ttf"));
+    let font = TTFont::new(&dir, "fonts/Roboto.zip/Roboto-Regular.ttf", 256, 256, 16.0, &['a']);
+    assert!(font.is_ok());
+
+    // A rewritten archive is reopened rather than served from the cache.
+    let scratch = TestDir::new("filesystems");
+    let write_zip = |contents: &[u8]| {
+        let mut zip = zip::ZipWriter::new(fs::File::create(scratch.join("data.zip")).unwrap());
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("a.txt", options).unwrap();
+        std::io::Write::write_all(&mut zip, contents).unwrap();
+        zip.finish().unwrap();
+    };
+    let zipped = DirectoryFs::new(scratch.to_str().unwrap());
+    write_zip(b"old");
+    assert_eq!(zipped.read("data.zip/a.txt").unwrap(), b"old");
+    write_zip(b"new");
+    fs::File::options()
+        .write(true)
+        .open(scratch.join("data.zip"))
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(zipped.read("data.zip/a.txt").unwrap(), b"new");
+
+    let mem = MemoryFs::new();
+    mem.insert("database.json", r#"{ "ttf_cfg": "ttf.json", "geometry_cfg": "geometry.json" }"#);
+    mem.insert("ttf.json", dir.read("ttf.json").unwrap());
+    mem.insert("geometry.json", dir.read("geometry.json").unwrap());
+    mem.insert("fonts/basic.ttf", dir.read("fonts/basic.ttf").unwrap());
+    mem.insert("model.gltf", dir.read("model.gltf").unwrap());
+
+    let mut db = Database::with_filesystem(Arc::new(mem)).unwrap();
+    assert!(db.fetch_ttf("font").unwrap().atlas.is_some());
+    assert!(!db.fetch_model("model").unwrap().meshes.is_empty());
+}
+
+#[test]
+fn test_validate() {
+    let sample = DirectoryFs::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database"));
+    let png = png_bytes(4, 4);
+
+    let mem = MemoryFs::new();
+    mem.insert(
+        "database.json",
+        r#"{ "image_cfg": "images.json", "atlas_cfg": "atlases.json", "ttf_cfg": "ttf.json" }"#,
+    );
+    mem.insert(
+        "images.json",
+        r#"{ "images": [
+            { "name": "a", "path": "a.png" },
+            { "name": "a", "path": "missing.png" }
+        ] }"#,
+    );
+    mem.insert(
+        "atlases.json",
+        r#"{ "atlases": [ { "name": "sheet", "path": "a.png", "entries": [
+            { "name": "ok", "id": 0, "bounds": { "x": 0, "y": 0, "w": 4, "h": 4 } },
+            { "name": "wide", "id": 0, "bounds": { "x": 2, "y": 0, "w": 4, "h": 4 } }
+        ] } ] }"#,
+    );
+    mem.insert(
+        "ttf.json",
+        r#"{ "fonts": [ { "name": "font", "path": "basic.ttf", "size": 12.0, "glyphs": "" } ] }"#,
+    );
+    mem.insert("a.png", png);
+    mem.insert("basic.ttf", sample.read("fonts/basic.ttf").unwrap());
+
+    let report = validate_filesystem(&mem);
+    assert_eq!(report.of_kind(IssueKind::DuplicateName).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::MissingFile).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::SpriteOutOfBounds).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::DuplicateSpriteId).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::EmptyGlyphs).count(), 1);
+    assert_eq!(report.issues.len(), 5);
+
+    // Loading refuses the duplicate instead of keeping one of the two.
+    let dup = MemoryFs::new();
+    dup.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    dup.insert("images.json", mem.read("images.json").unwrap());
+    match Database::with_filesystem(Arc::new(dup)) {
+        Err(Error::LoadingError(err)) => {
+            assert_eq!(err.entry, "a");
+            assert!(err.path.ends_with("/images.json"));
+        }
+        _ => panic!("duplicate names must fail to load"),
+    }
+
+    mem.insert("ttf.json", "{ \"typo\": [] }");
+    let report = validate_filesystem(&mem);
+    assert_eq!(report.of_kind(IssueKind::InvalidConfig).count(), 1);
+
+    mem.insert(
+        "ttf.json",
+        r#"{ "fonts": [ { "name": "font", "path": "basic.ttf", "size": 12.0, "glyps": "abc" } ], "extra": 1 }"#,
+    );
+    let report = validate_filesystem(&mem);
+    let mut unknown: Vec<String> = report.of_kind(IssueKind::UnknownKey).map(|i| i.message.clone()).collect();
+    unknown.sort();
+    assert_eq!(unknown, ["unknown key extra", "unknown key fonts[0].glyps"]);
+}
+
+#[test]
+fn test_layers() {
+    let base = MemoryFs::new();
+    base.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    base.insert(
+        "images.json",
+        r#"{ "images": [ { "name": "a", "path": "a.png" }, { "name": "b", "path": "b.png" } ] }"#,
+    );
+    base.insert("a.png", png_bytes(4, 4));
+    base.insert("b.png", png_bytes(4, 4));
+
+    let overlay = MemoryFs::new();
+    overlay.insert("database.json", r#"{ "image_cfg": "mod.json" }"#);
+    overlay.insert(
+        "mod.json",
+        r#"{ "images": [ { "name": "a", "path": "a.png" }, { "name": "c", "path": "c.png" } ] }"#,
+    );
+    overlay.insert("a.png", png_bytes(8, 8));
+    overlay.insert("c.png", png_bytes(2, 2));
+
+    let mut db = Database::with_layers(vec![Arc::new(base), Arc::new(overlay)]).unwrap();
+    assert_eq!(db.layers().len(), 2);
+    assert_eq!(db.entry_layer(AssetCategory::Image, "a"), Some(1));
+    assert_eq!(db.entry_layer(AssetCategory::Image, "b"), Some(0));
+    assert_eq!(db.entry_layer(AssetCategory::Image, "c"), Some(1));
+
+    assert_eq!(db.fetch_image("a").unwrap().size, [8, 8]);
+    assert_eq!(db.fetch_image("b").unwrap().size, [4, 4]);
+    assert_eq!(db.fetch_image("c").unwrap().size, [2, 2]);
+    assert!(db.validate().is_ok());
+}
+
+#[test]
+fn test_authoring() {
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "ttf_cfg": "ttf.json" }"#);
+    fs.insert(
+        "ttf.json",
+        r#"{ "fonts": [ { "name": "font", "path": "basic.ttf", "size": 12.0 } ] }"#,
+    );
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    db.add_image(ImageJSONEntry {
+        name: "zebra".to_string(),
+        path: "zebra.png".to_string(),
+        tags: None,
+        depends_on: None,
+        variants: None,
+    })
+    .unwrap();
+    db.add_image(ImageJSONEntry {
+        name: "apple".to_string(),
+        path: "apple.png".to_string(),
+        tags: None,
+        depends_on: None,
+        variants: None,
+    })
+    .unwrap();
+    assert!(db
+        .add_image(ImageJSONEntry {
+            name: "apple".to_string(),
+            path: "other.png".to_string(),
+            tags: None,
+            depends_on: None,
+            variants: None,
+        })
+        .is_err());
+
+    db.edit_ttf("font", |cfg| cfg.size = 24.0).unwrap();
+    db.rename(AssetCategory::Image, "zebra", "banana").unwrap();
+    db.remove(AssetCategory::Image, "apple").unwrap();
+    db.add_image(ImageJSONEntry {
+        name: "cherry".to_string(),
+        path: "cherry.png".to_string(),
+        tags: None,
+        depends_on: None,
+        variants: None,
+    })
+    .unwrap();
+    assert!(db.has_unsaved_changes());
+    db.save().unwrap();
+    assert!(!db.has_unsaved_changes());
+
+    let images: ImageJSON = serde_json::from_slice(&fs.read("images.json").unwrap()).unwrap();
+    let names: Vec<&str> = images.images.iter().map(|i| i.name.as_str()).collect();
+    assert_eq!(names, ["banana", "cherry"]);
+
+    let info: DatabaseJSON = serde_json::from_slice(&fs.read("database.json").unwrap()).unwrap();
+    assert_eq!(info.image_cfg.as_deref(), Some("images.json"));
+    let ttfs: TTFJSON = serde_json::from_slice(&fs.read("ttf.json").unwrap()).unwrap();
+    assert_eq!(ttfs.fonts[0].size, 24.0);
+
+    let reopened = Database::with_filesystem(fs).unwrap();
+    assert_eq!(reopened.entry_layer(AssetCategory::Image, "banana"), Some(0));
+
+    // Removing or renaming an overlay entry uncovers the entry it overrode, and
+    // saving keeps the config keys the database doesn't model.
+    let base = Arc::new(MemoryFs::new());
+    base.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    base.insert(
+        "images.json",
+        r#"{ "images": [ { "name": "a", "path": "a.png" }, { "name": "b", "path": "b.png" } ] }"#,
+    );
+    let overlay = Arc::new(MemoryFs::new());
+    overlay.insert("database.json", r#"{ "image_cfg": "images.json", "author": "modder" }"#);
+    overlay.insert(
+        "images.json",
+        r#"{ "images": [ { "name": "a", "path": "mod_a.png" }, { "name": "b", "path": "mod_b.png" },
+                         { "name": "d", "path": "d.png", "note": "hero" } ], "author": "modder" }"#,
+    );
+
+    let mut db = Database::with_layers(vec![base, overlay.clone()]).unwrap();
+    db.remove(AssetCategory::Image, "a").unwrap();
+    assert_eq!(db.entry_layer(AssetCategory::Image, "a"), Some(0));
+    assert_eq!(db.images["a"].cfg.path, "a.png");
+    db.rename(AssetCategory::Image, "b", "c").unwrap();
+    assert_eq!(db.entry_layer(AssetCategory::Image, "b"), Some(0));
+    assert_eq!(db.entry_layer(AssetCategory::Image, "c"), Some(1));
+    db.save().unwrap();
+
+    let saved: serde_json::Value = serde_json::from_slice(&overlay.read("database.json").unwrap()).unwrap();
+    assert_eq!(saved["author"], "modder");
+    let saved: serde_json::Value = serde_json::from_slice(&overlay.read("images.json").unwrap()).unwrap();
+    assert_eq!(saved["author"], "modder");
+    assert_eq!(saved["images"][1]["note"], "hero");
+    let images: ImageJSON = serde_json::from_slice(&overlay.read("images.json").unwrap()).unwrap();
+    let names: Vec<&str> = images.images.iter().map(|i| i.name.as_str()).collect();
+    assert_eq!(names, ["c", "d"]);
+}
+
+#[test]
+fn test_query() {
+    assert!(glob_match("tree_*", "tree_oak"));
+    assert!(glob_match("*_?", "rock_a"));
+    assert!(glob_match("*", ""));
+    assert!(!glob_match("tree_*", "bush_oak"));
+    assert!(!glob_match("a?c", "ac"));
+
+    let fs = MemoryFs::new();
+    fs.insert("database.json", r#"{ "image_cfg": "images.json", "ttf_cfg": "ttf.json" }"#);
+    fs.insert(
+        "images.json",
+        r#"{ "images": [
+            { "name": "tree_oak", "path": "oak.png", "tags": ["forest", "tree"] },
+            { "name": "tree_pine", "path": "pine.png", "tags": ["tree"] },
+            { "name": "rock", "path": "rock.png", "tags": ["forest"] }
+        ] }"#,
+    );
+    fs.insert(
+        "ttf.json",
+        r#"{ "fonts": [ { "name": "tree_font", "path": "basic.ttf", "size": 12.0 } ] }"#,
+    );
+
+    let db = Database::with_filesystem(Arc::new(fs)).unwrap();
+    assert_eq!(db.names(AssetCategory::Image).count(), 3);
+    assert_eq!(db.ttf_configs().next().unwrap().name, "tree_font");
+
+    let names = |keys: Vec<AssetKey>| keys.into_iter().map(|k| k.name).collect::<Vec<_>>();
+    assert_eq!(names(db.find_by_tag("forest")), ["rock", "tree_oak"]);
+    assert_eq!(names(db.find_by_name("tree_*")), ["tree_oak", "tree_pine", "tree_font"]);
+    assert_eq!(names(db.find_in_category(AssetCategory::TTF)), ["tree_font"]);
+    let query = AssetQuery::new().category(AssetCategory::Image).tag("tree").name("*oak");
+    assert_eq!(names(db.query(&query)), ["tree_oak"]);
+}
+
+#[test]
+fn test_asset_ids() {
+    let id = AssetId::of(AssetCategory::TTF, "font");
+    assert_eq!(id, AssetId::of(AssetCategory::TTF, "font"));
+    assert_ne!(id, AssetId::of(AssetCategory::Image, "font"));
+    assert_eq!(id, AssetKey::new(AssetCategory::TTF, "font").id());
+
+    let mut table = IdTable::default();
+    let key = AssetKey::new(AssetCategory::Image, "a");
+    assert!(table.insert(key.clone()).is_ok());
+    assert!(table.insert(key.clone()).is_ok());
+    assert!(table.is_free(&key));
+
+    let mut db = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database")).unwrap();
+    assert_eq!(db.id_of(AssetCategory::TTF, "font"), Some(id));
+    assert_eq!(db.id_of(AssetCategory::TTF, "missing"), None);
+    assert_eq!(db.lookup_id(id), Some(&AssetKey::new(AssetCategory::TTF, "font")));
+    assert!(db.fetch_ttf_by_id(id).is_ok());
+    assert!(db.fetch_image_by_id(id).is_err());
+}
+
+#[test]
+fn test_asset_id_collisions() {
+    // Two image names with the same FNV-1a hash.
+    let (a, b) = ("6b3d72d6e16568c5", "ab7385c3b289891d");
+    let id = AssetId::of(AssetCategory::Image, a);
+    assert_eq!(id, AssetId::of(AssetCategory::Image, b));
+
+    let png = png_bytes(2, 2);
+    let both = format!(
+        r#"{{ "images": [ {{ "name": "{}", "path": "a.png" }}, {{ "name": "{}", "path": "a.png" }} ] }}"#,
+        a, b
+    );
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "image_cfg": "images.json" }"#);
+    fs.insert("images.json", both.clone());
+    fs.insert("a.png", png);
+    let err = Database::with_filesystem(fs.clone()).err().unwrap();
+    assert!(format!("{:?}", err).contains("collision"));
+
+    fs.insert(
+        "images.json",
+        format!(r#"{{ "images": [ {{ "name": "{}", "path": "a.png" }}, {{ "name": "c", "path": "a.png" }} ] }}"#, a),
+    );
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+
+    // Failed edits leave the database as it was.
+    let cfg = ImageJSONEntry {
+        name: b.to_string(),
+        ..db.images[a].cfg.clone()
+    };
+    assert!(db.add_image(cfg).is_err());
+    assert!(!db.contains(AssetCategory::Image, b));
+    assert!(db.rename(AssetCategory::Image, "c", b).is_err());
+    assert!(db.contains(AssetCategory::Image, "c"));
+    assert!(db.edit_image("c", |c| c.name = b.to_string()).is_err());
+    assert!(db.contains(AssetCategory::Image, "c"));
+    assert!(!db.has_unsaved_changes());
+    assert_eq!(db.lookup_id(id), Some(&AssetKey::new(AssetCategory::Image, a)));
+
+    // So does a reload that would introduce a collision.
+    fs.insert("images.json", both);
+    assert!(db.poll_changes().is_err());
+    assert!(db.contains(AssetCategory::Image, "c"));
+    assert!(!db.contains(AssetCategory::Image, b));
+    assert_eq!(db.fetch_image_by_id(id).unwrap().key().name, a);
+}
+
+#[test]
+fn test_dependencies() {
+    let png = png_bytes(4, 4);
+
+    let fs = MemoryFs::new();
+    fs.insert("database.json", r#"{ "image_cfg": "images.json", "atlas_cfg": "atlases.json" }"#);
+    fs.insert(
+        "images.json",
+        r#"{ "images": [
+            { "name": "sheet", "path": "sheet.png" },
+            { "name": "palette", "path": "palette.png" }
+        ] }"#,
+    );
+    fs.insert(
+        "atlases.json",
+        r#"{ "atlases": [ { "name": "ui", "path": "sheet.png", "depends_on": [
+            { "category": "Image", "name": "sheet" }
+        ] } ] }"#,
+    );
+    fs.insert("sheet.png", png.clone());
+    fs.insert("palette.png", png);
+
+    let mut db = Database::with_filesystem(Arc::new(fs)).unwrap();
+    let sheet = AssetKey::new(AssetCategory::Image, "sheet");
+    assert_eq!(db.dependencies(AssetCategory::Atlas, "ui"), vec![sheet.clone()]);
+
+    let loaded = db.fetch_with_dependencies(AssetCategory::Atlas, "ui").unwrap();
+    let keys: Vec<&AssetKey> = loaded.iter().map(|a| a.key()).collect();
+    assert_eq!(keys, [&AssetKey::new(AssetCategory::Atlas, "ui"), &sheet]);
+    assert_eq!(
+        db.loaded_dependents(AssetCategory::Image, "sheet"),
+        [AssetKey::new(AssetCategory::Atlas, "ui")]
+    );
+
+    assert!(db.unload(AssetCategory::Image, "sheet").is_err());
+    assert!(db.unload(AssetCategory::Atlas, "ui").is_ok());
+    assert!(db.loaded_dependents(AssetCategory::Image, "sheet").is_empty());
+    assert!(db.unload(AssetCategory::Image, "sheet").is_ok());
+
+    // Evicting the atlas frees the image it held for the same budget check.
+    drop(loaded);
+    let loaded = db.fetch_with_dependencies(AssetCategory::Atlas, "ui").unwrap();
+    drop(loaded);
+    db.set_memory_budget(MemoryBudget::unlimited().with_global(0));
+    assert_eq!(db.resident_bytes(), 0);
+    assert!(db.loaded_dependents(AssetCategory::Image, "sheet").is_empty());
+}
+
+#[test]
+fn test_fetch_particle_system() {
+    let mut db = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database")).unwrap();
+
+    let particle = db.fetch_particle_system("a").unwrap();
+    assert!(particle.image.size[0] >= 32 && particle.image.size[1] >= 32);
+    let animation = particle.animation("test").unwrap();
+    assert_eq!(animation.time_per_frame_ms, 4.0);
+    assert_eq!(animation.sprites.len(), 1);
+    assert!(particle.animation_by_id(1).is_none());
+
+    let id = db.id_of(AssetCategory::Particle, "a").unwrap();
+    assert!(db.fetch_particle_system_by_id(id).is_ok());
+    assert_eq!(db.validate().of_kind(IssueKind::SpriteOutOfBounds).count(), 0);
+}
+
+#[test]
+fn test_shaders() {
+    let mut db = Database::new(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_database")).unwrap();
+    let mut names: Vec<&str> = db.shader_names().collect();
+    names.sort();
+    assert_eq!(names, ["stdcompute", "stdrender"]);
+
+    let render = db.fetch_shader("stdrender").unwrap();
+    assert!(render.stage(ShaderStage::Vertex).is_some());
+    assert!(render.stage(ShaderStage::Fragment).is_some());
+    assert!(!render.is_compute());
+    assert!(db.fetch_shader("stdcompute").unwrap().is_compute());
+
+    let fs = MemoryFs::new();
+    fs.insert("database.json", r#"{ "shader_path": "shaders" }"#);
+    fs.insert(
+        "shaders/blit.wgsl",
+        r#"
+        @vertex
+        fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+            return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return vec4<f32>(1.0);
+        }
+        "#,
+    );
+    let spirv = &render.stage(ShaderStage::Vertex).unwrap().spirv;
+    let bytes: Vec<u8> = spirv.iter().flat_map(|w| w.to_le_bytes()).collect();
+    fs.insert("shaders/post/copy.vert.spv", bytes);
+    fs.insert("shaders/broken.frag.glsl", "void main() { nope }");
+    fs.insert("shaders/readme.txt", "not a shader");
+    fs.insert("shaders/twice.wgsl", fs.read("shaders/blit.wgsl").unwrap());
+    fs.insert("shaders/twice.vert.spv", fs.read("shaders/post/copy.vert.spv").unwrap());
+
+    let mut db = Database::with_filesystem(Arc::new(fs)).unwrap();
+    let blit = db.fetch_shader("blit").unwrap();
+    assert_eq!(blit.stage(ShaderStage::Vertex).unwrap().entry_point, "vs_main");
+    assert_eq!(blit.stage(ShaderStage::Fragment).unwrap().entry_point, "fs_main");
+    assert_eq!(&db.fetch_shader("post/copy").unwrap().stages[0].spirv, spirv);
+    assert!(db.fetch_shader("broken").is_err());
+    assert!(db.fetch_shader("readme").is_err());
+    assert!(db.fetch_shader("twice").is_err());
+
+    // Shaders have ids and show up in queries like other entries.
+    let id = db.id_of(AssetCategory::Shader, "blit").unwrap();
+    assert!(db.fetch_shader_by_id(id).unwrap().ptr_eq(&blit));
+    assert!(db.fetch_image_by_id(id).is_err());
+    assert_eq!(db.find_by_name("post/*"), [AssetKey::new(AssetCategory::Shader, "post/copy")]);
+    assert_eq!(db.find_in_category(AssetCategory::Shader).len(), 4);
+}
+
+#[test]
+fn test_shader_reload() {
+    let shader = |value: f32| {
+        format!(
+            "@fragment fn fs_main() -> @location(0) vec4<f32> {{ return vec4<f32>({:.1}); }}",
+            value
+        )
+    };
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "shader_path": "shaders" }"#);
+    fs.insert("shaders/fill.wgsl", shader(1.0));
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    let fill = AssetKey::new(AssetCategory::Shader, "fill");
+    let first = db.fetch_shader("fill").unwrap();
+    assert_eq!(first.key(), &fill);
+    assert_eq!(db.category_bytes(AssetCategory::Shader), first.byte_size());
+    assert!(db.poll_changes().unwrap().is_empty());
+
+    fs.insert("shaders/fill.wgsl", shader(0.5));
+    fs.insert("shaders/extra.wgsl", shader(0.0));
+    let mut changed = db.poll_changes().unwrap();
+    changed.sort();
+    assert_eq!(changed, [AssetKey::new(AssetCategory::Shader, "extra"), fill.clone()]);
+    assert_eq!(db.category_bytes(AssetCategory::Shader), 0);
+
+    let second = db.fetch_shader("fill").unwrap();
+    assert_ne!(first.stages[0].spirv, second.stages[0].spirv);
+    assert!(db.fetch_shader("extra").is_ok());
+
+    // Shaders are evicted like other entries once their handles are dropped.
+    drop((first, second));
+    db.set_memory_budget(MemoryBudget::unlimited().with_category(AssetCategory::Shader, 0));
+    assert_eq!(db.category_bytes(AssetCategory::Shader), 0);
+}
+
+#[test]
+fn test_render_graph() {
+    let fs = MemoryFs::new();
+    fs.insert("database.json", r#"{ "render_graph_path": "render_graph.json" }"#);
+    fs.insert(
+        "render_graph.json",
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/config/render_graph.json")),
+    );
+    let fs = Arc::new(fs);
+    let db = Database::with_filesystem(fs.clone()).unwrap();
+
+    let graph = db.fetch_render_graph().unwrap();
+    assert_eq!(graph.render_pass.size, [1280, 1024]);
+    assert_eq!(graph.render_mask("shadow"), Some(1));
+    let base = graph.node("base_pass").unwrap();
+    assert_eq!(base.pipeline, PipelineJSON::Graphics("stdrender".to_string()));
+    assert_eq!(graph.subpass("main-pass").unwrap().attachments[1].kind, AttachmentType::Depth);
+    let order: Vec<&str> = graph.ordered_nodes().map(|n| n.name.as_str()).collect();
+    assert_eq!(order, ["base_pass"]);
+    assert!(db.validate().is_ok());
+
+    fs.insert(
+        "render_graph.json",
+        r#"{
+            "render_pass": { "size": [64, 64], "subpasses": [ { "name": "main", "attachments": [] } ] },
+            "render_mask": { "standard": 0 },
+            "nodes": [ {
+                "name": "draw",
+                "pipeline": { "graphics": "stdrender" },
+                "subpass": "missing",
+                "render_masks": ["standard", "shadow"]
+            } ],
+            "execution_order": ["draw", "blur", "OUTPUT"]
+        }"#,
+    );
+    assert!(db.fetch_render_graph().is_err());
+    let report = db.validate();
+    assert_eq!(report.of_kind(IssueKind::UnknownSubpass).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::UndeclaredRenderMask).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::UnknownNode).count(), 1);
+    assert_eq!(report.issues.len(), 3);
+}
+
+#[test]
+fn test_migrations() {
+    let png = png_bytes(128, 128);
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "image_cfg": "sprites.json", "atlas_cfg": "sprite_sheets.json" }"#);
+    fs.insert(
+        "sprites.json",
+        r#"{ "sprites": [ { "name": "picture", "image_path": "sheet.png" } ], "author": "modder" }"#,
+    );
+    fs.insert(
+        "sprite_sheets.json",
+        r#"{ "sprite_sheets": [ { "name": "character", "image_path": "sheet.png", "sprites": [
+            { "name": "idle", "id": 0, "bounds": { "x": 0, "y": 0, "w": 64, "h": 64 } }
+        ] } ] }"#,
+    );
+    fs.insert("sheet.png", png);
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    let upgraded: Vec<&str> = db.migrations().iter().map(|m| m.path.as_str()).collect();
+    assert_eq!(upgraded, ["database.json", "sprites.json", "sprite_sheets.json"]);
+    assert!(db.migrations().iter().all(|m| m.from == 0 && m.to == CONFIG_VERSION));
+    assert!(db.fetch_image("picture").is_ok());
+    assert_eq!(db.fetch_atlas("character").unwrap().sprites.len(), 1);
+
+    // Re-reading a file replaces its records instead of adding to them.
+    fs.insert("sprites.json", fs.read("sprites.json").unwrap());
+    db.poll_changes().unwrap();
+    assert_eq!(db.migrations().len(), 3);
+
+    assert_eq!(db.upgrade_configs().unwrap().len(), 3);
+    let images: serde_json::Value = serde_json::from_slice(&fs.read("sprites.json").unwrap()).unwrap();
+    assert_eq!(images["author"], "modder");
+    assert_eq!(images["images"][0]["path"], "sheet.png");
+    let atlases: AtlasJSON = serde_json::from_slice(&fs.read("sprite_sheets.json").unwrap()).unwrap();
+    assert_eq!(atlases.version, CONFIG_VERSION);
+    assert_eq!(atlases.atlases[0].path, "sheet.png");
+    assert!(db.upgrade_configs().unwrap().is_empty());
+    assert!(db.poll_changes().unwrap().is_empty());
+
+    let reopened = Database::with_filesystem(fs.clone()).unwrap();
+    assert!(reopened.migrations().is_empty());
+    assert!(reopened.contains(AssetCategory::Atlas, "character"));
+
+    fs.insert("database.json", format!(r#"{{ "version": {} }}"#, CONFIG_VERSION + 1));
+    assert!(Database::with_filesystem(fs.clone()).is_err());
+    fs.insert("database.json", r#"{ "version": 4294967297 }"#);
+    let err = Database::with_filesystem(fs.clone()).err().unwrap();
+    assert!(err.to_string().contains("invalid config version"));
+
+    // Current configs report where they went wrong.
+    fs.insert("database.json", format!("{{\n  \"version\": {},\n  \"image_cfg\": 3\n}}", CONFIG_VERSION));
+    let err = Database::with_filesystem(fs).err().unwrap();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+}
+
+#[test]
+fn test_config_schemas() {
+    let fs = MemoryFs::new();
+    let written = export_schemas(&fs, "schemas").unwrap();
+    assert_eq!(written.len(), config_schemas().len());
+    assert!(written.contains(&"schemas/canvas.schema.json".to_string()));
+
+    let canvas: serde_json::Value =
+        serde_json::from_slice(&fs.read("schemas/canvas.schema.json").unwrap()).unwrap();
+    let defs = &canvas["definitions"];
+    assert_eq!(defs["SampleCount"]["enum"], serde_json::json!(["S1", "S2"]));
+    assert!(defs["Format"]["enum"].as_array().unwrap().contains(&"RGBA8".into()));
+    assert!(defs["Viewport"]["properties"]["scissor"].is_object());
+
+    let atlases: serde_json::Value =
+        serde_json::from_slice(&fs.read("schemas/atlases.schema.json").unwrap()).unwrap();
+    assert!(atlases["definitions"]["Rect2D"]["properties"]["w"].is_object());
+
+    // Schemas apply to the configs the root config declares, whatever their names.
+    let info: DatabaseJSON =
+        serde_json::from_str(r#"{ "ttf_cfg": "fonts/fonts.json", "atlas_cfg": "sprite_sheets.json" }"#).unwrap();
+    let settings = vscode_schema_settings("schemas", &info);
+    let file_match = |url: &str| {
+        let schemas = settings["json.schemas"].as_array().unwrap();
+        schemas.iter().find(|s| s["url"] == url).unwrap()["fileMatch"].clone()
+    };
+    assert_eq!(file_match("./schemas/ttf.schema.json"), serde_json::json!(["**/fonts/fonts.json"]));
+    assert_eq!(file_match("./schemas/atlases.schema.json"), serde_json::json!(["**/sprite_sheets.json"]));
+    assert_eq!(file_match("./schemas/images.schema.json"), serde_json::json!([]));
+    assert_eq!(file_match("./schemas/database.schema.json"), serde_json::json!(["**/database.json"]));
+
+    // The stand-ins for dashi types must describe what dashi reads and writes.
+    let mut defs = serde_json::Map::new();
+    for schema in config_schemas() {
+        let schema = serde_json::to_value(&schema.schema).unwrap();
+        if let Some(d) = schema["definitions"].as_object() {
+            defs.extend(d.clone());
+        }
+    }
+    fn variants<T: serde::de::DeserializeOwned>(defs: &serde_json::Map<String, serde_json::Value>, name: &str) {
+        for variant in defs[name]["enum"].as_array().unwrap() {
+            let read: Result<T, _> = serde_json::from_value(variant.clone());
+            assert!(read.is_ok(), "dashi {} has no variant {}", name, variant);
+        }
+    }
+    variants::<dashi::Format>(&defs, "Format");
+    variants::<dashi::SampleCount>(&defs, "SampleCount");
+    variants::<dashi::LoadOp>(&defs, "LoadOp");
+    variants::<dashi::StoreOp>(&defs, "StoreOp");
+
+    // And every dashi variant needs a stand-in. The matches have no catch-all arm,
+    // so a variant added to dashi stops them compiling until it is listed here.
+    fn listed<T: serde::Serialize>(defs: &serde_json::Map<String, serde_json::Value>, name: &str, all: &[T]) {
+        let mut expected: Vec<String> = all.iter().map(|v| serde_json::to_value(v).unwrap().to_string()).collect();
+        let mut found: Vec<String> = defs[name]["enum"].as_array().unwrap().iter().map(|v| v.to_string()).collect();
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected, "{} stand-in doesn't list every dashi variant", name);
+    }
+    let formats = [
+        dashi::Format::RGB8,
+        dashi::Format::BGRA8,
+        dashi::Format::BGRA8Unorm,
+        dashi::Format::RGBA8,
+        dashi::Format::RGBA32F,
+        dashi::Format::D24S8,
+        dashi::Format::R8Sint,
+        dashi::Format::R8Uint,
+    ];
+    for format in formats {
+        match format {
+            dashi::Format::RGB8
+            | dashi::Format::BGRA8
+            | dashi::Format::BGRA8Unorm
+            | dashi::Format::RGBA8
+            | dashi::Format::RGBA32F
+            | dashi::Format::D24S8
+            | dashi::Format::R8Sint
+            | dashi::Format::R8Uint => {}
+        }
+    }
+    listed(&defs, "Format", &formats);
+    let samples = [dashi::SampleCount::S1, dashi::SampleCount::S2];
+    for samples in samples {
+        match samples {
+            dashi::SampleCount::S1 | dashi::SampleCount::S2 => {}
+        }
+    }
+    listed(&defs, "SampleCount", &samples);
+    let loads = [dashi::LoadOp::Load, dashi::LoadOp::Clear, dashi::LoadOp::DontCare];
+    for load in loads {
+        match load {
+            dashi::LoadOp::Load | dashi::LoadOp::Clear | dashi::LoadOp::DontCare => {}
+        }
+    }
+    listed(&defs, "LoadOp", &loads);
+    let stores = [dashi::StoreOp::Store, dashi::StoreOp::DontCare];
+    for store in stores {
+        match store {
+            dashi::StoreOp::Store | dashi::StoreOp::DontCare => {}
+        }
+    }
+    listed(&defs, "StoreOp", &stores);
+
+    // An instance with every field of the stand-in round-trips unchanged.
+    fn sample(defs: &serde_json::Map<String, serde_json::Value>, name: &str) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+        for (field, schema) in defs[name]["properties"].as_object().unwrap() {
+            let value = match schema["$ref"].as_str() {
+                Some(r) => sample(defs, r.trim_start_matches("#/definitions/")),
+                None if schema["type"] == "integer" => serde_json::json!(7),
+                None => serde_json::json!(0.5),
+            };
+            object.insert(field.clone(), value);
+        }
+        serde_json::Value::Object(object)
+    }
+    fn fields<T: serde::de::DeserializeOwned + serde::Serialize>(
+        defs: &serde_json::Map<String, serde_json::Value>,
+        name: &str,
+    ) {
+        let sample = sample(defs, name);
+        let read: T = serde_json::from_value(sample.clone()).unwrap();
+        assert_eq!(serde_json::to_value(read).unwrap(), sample, "{} fields differ from dashi", name);
+    }
+    fields::<dashi::Rect2D>(&defs, "Rect2D");
+    fields::<dashi::FRect2D>(&defs, "FRect2D");
+    fields::<dashi::Viewport>(&defs, "Viewport");
+}
+
+#[test]
+fn test_config_formats() {
+    let png = png_bytes(4, 4);
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert(
+        "database.toml",
+        "# Configs may mix formats.\nimage_cfg = \"images.yaml\"\natlas_cfg = \"atlases.ron\"\n",
+    );
+    fs.insert(
+        "images.yaml",
+        "images:\n  - name: picture\n    path: sheet.png\n    depends_on:\n      - { category: Atlas, name: character }\n",
+    );
+    fs.insert(
+        "atlases.ron",
+        r#"// Comments are fine here.
+        (
+            version: 1,
+            atlases: [
+                (name: "character", path: "sheet.png", entries: [
+                    (name: "idle", id: 0, bounds: (x: 0, y: 0, w: 4, h: 4)),
+                ]),
+            ],
+        )"#,
+    );
+    fs.insert("sheet.png", png);
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    assert!(db.fetch_image("picture").is_ok());
+    assert_eq!(db.fetch_atlas("character").unwrap().sprites.len(), 1);
+    assert!(db.validate().is_ok());
+
+    db.add_image(ImageJSONEntry {
+        name: "other".to_string(),
+        path: "sheet.png".to_string(),
+        tags: None,
+        depends_on: None,
+        variants: None,
+    })
+    .unwrap();
+    db.save().unwrap();
+    assert!(fs.read_to_string("images.yaml").unwrap().contains("name: other"));
+    assert!(fs.read_to_string("database.toml").unwrap().contains("image_cfg = \"images.yaml\""));
+    let reopened = Database::with_filesystem(fs.clone()).unwrap();
+    assert!(reopened.contains(AssetCategory::Image, "other"));
+
+    fs.insert("atlases.ron", "(atlases: [(name: 3)])");
+    let error = Database::with_filesystem(fs.clone()).err().unwrap().to_string();
+    assert!(error.contains("RON"), "{}", error);
+    fs.insert("atlases.ron", "(atlases: [])");
+
+    // Older RON layouts go through the migrations like the other formats.
+    fs.insert("database.toml", "image_cfg = \"images.ron\"\natlas_cfg = \"atlases.ron\"\n");
+    fs.insert("images.ron", r#"(sprites: [(name: "legacy", image_path: "sheet.png")])"#);
+    let db = Database::with_filesystem(fs.clone()).unwrap();
+    assert!(db.contains(AssetCategory::Image, "legacy"));
+    assert!(db.migrations().iter().any(|m| m.path == "images.ron" && m.from == 0));
+
+    // Typed parses keep the position of the error.
+    fs.insert("database.toml", "image_cfg = \"images.yaml\"\natlas_cfg = 3\n");
+    let error = Database::with_filesystem(fs.clone()).err().unwrap().to_string();
+    assert!(error.contains("line 2"), "{}", error);
+    fs.insert("database.toml", "image_cfg = \"images.yaml\"\n");
+    fs.insert("images.yaml", "version: 1\nimages:\n  - name: picture\n    path: [3]\n");
+    let error = Database::with_filesystem(fs.clone()).err().unwrap().to_string();
+    assert!(error.contains("line 4"), "{}", error);
+
+    fs.insert("database.json", "{}");
+    let error = Database::with_filesystem(fs).err().unwrap().to_string();
+    assert!(error.contains("several root configs"), "{}", error);
+}
+
+#[test]
+fn test_variants() {
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "image_cfg": "images.json", "atlas_cfg": "atlases.json" }"#);
+    fs.insert(
+        "images.json",
+        r#"{ "images": [ { "name": "logo", "path": "logo.png", "variants": [
+            { "when": ["2x"], "path": "logo@2x.png" },
+            { "when": ["2x", "desktop"], "path": "logo@2x-desktop.png" }
+        ] } ] }"#,
+    );
+    fs.insert(
+        "atlases.json",
+        r#"{ "atlases": [ { "name": "ui", "path": "logo.png",
+            "entries": [ { "name": "button", "id": 0, "bounds": { "x": 0, "y": 0, "w": 4, "h": 4 } } ],
+            "variants": [ { "when": ["2x"], "path": "logo@2x.png",
+                "entries": [ { "name": "button", "id": 0, "bounds": { "x": 0, "y": 0, "w": 8, "h": 8 } } ] } ]
+        } ] }"#,
+    );
+    fs.insert("logo.png", png_bytes(4, 4));
+    fs.insert("logo@2x.png", png_bytes(8, 8));
+    fs.insert("logo@2x-desktop.png", png_bytes(16, 16));
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    assert_eq!(db.fetch_image("logo").unwrap().size, [4, 4]);
+
+    let changed = db.set_variant_profile(VariantProfile::new(&["2x"]));
+    assert!(changed.contains(&AssetKey::new(AssetCategory::Image, "logo")));
+    assert_eq!(db.fetch_image("logo").unwrap().size, [8, 8]);
+    let atlas = db.fetch_atlas("ui").unwrap();
+    assert_eq!((atlas.image.size, atlas.sprites[0].bounds.w), ([8, 8], 8));
+
+    db.set_variant_profile(VariantProfile::new(&["desktop", "2x"]));
+    assert_eq!(db.fetch_image("logo").unwrap().size, [16, 16]);
+    assert_eq!(db.fetch_atlas("ui").unwrap().image.size, [8, 8]);
+
+    assert_eq!(db.set_variant_profile(VariantProfile::new(&["desktop", "2x", "hdr"])), Vec::new());
+    db.set_variant_profile(VariantProfile::new(&["desktop"]));
+    assert_eq!(db.fetch_image("logo").unwrap().size, [4, 4]);
+    assert!(db.validate().is_ok());
+
+    // A variant without its own sprites uses the base ones, which must fit its image.
+    let changed = db
+        .edit_atlas("ui", |atlas| {
+            atlas.variants.as_mut().unwrap().push(AtlasVariantJSON {
+                when: vec!["desktop".to_string()],
+                path: "logo-small.png".to_string(),
+                entries: None,
+            })
+        })
+        .unwrap();
+    assert_eq!(changed, vec![AssetKey::new(AssetCategory::Atlas, "ui")]);
+    fs.insert("logo-small.png", png_bytes(2, 2));
+    db.save().unwrap();
+    let report = db.validate();
+    let issue = report.of_kind(IssueKind::SpriteOutOfBounds).next().unwrap();
+    assert!(issue.message.contains("sprite button of variant logo-small.png"));
+    db.edit_atlas("ui", |atlas| {
+        atlas.variants.as_mut().unwrap().pop();
+    })
+    .unwrap();
+    db.save().unwrap();
+
+    fs.remove("logo@2x-desktop.png");
+    assert!(db.validate().issues.iter().any(|i| i.kind == IssueKind::MissingFile));
+}
+
+#[test]
+fn test_localization() {
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert(
+        "database.json",
+        r#"{ "localization_cfg": "localization.json", "ttf_cfg": "ttf.json" }"#,
+    );
+    fs.insert(
+        "localization.json",
+        r#"{ "default_locale": "en", "locales": [
+            { "locale": "en", "strings": { "greeting": "Hello, {name}!", "quit": "Quit", "braces": "{{x}}" } },
+            { "locale": "fr", "strings": { "greeting": "Bonjour, {name} !" } },
+            { "locale": "fr-CA", "strings": { "quit": "Quitter" } }
+        ] }"#,
+    );
+    fs.insert(
+        "ttf.json",
+        r#"{ "fonts": [ { "name": "ui", "path": "basic.ttf", "size": 12.0, "locale": "fr-CA" } ] }"#,
+    );
+    fs.insert("basic.ttf", include_bytes!("../../sample_database/fonts/basic.ttf").to_vec());
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    assert_eq!(db.localization().fallback_chain("fr-CA"), ["fr-CA", "fr", "en"]);
+    assert_eq!(db.localize("fr-CA", "quit").unwrap(), "Quitter");
+    assert_eq!(db.localize("fr", "quit").unwrap(), "Quit");
+    assert_eq!(
+        db.localize_format("fr-CA", "greeting", &[("name", "Zoé")]).unwrap(),
+        "Bonjour, Zoé !"
+    );
+    assert_eq!(db.localize_format("en", "braces", &[("x", "1")]).unwrap(), "{x}");
+    assert!(db.localize("de", "missing").is_err());
+
+    let glyphs = db.locale_glyphs("fr-CA");
+    assert!(glyphs.contains(&'!') && glyphs.contains(&'B') && glyphs.contains(&'{'));
+    assert!(!glyphs.contains(&'H') && !glyphs.contains(&'m'));
+
+    let font = db.fetch_ttf("ui").unwrap();
+    assert!(glyphs.iter().all(|c| font.glyphs.contains_key(c)));
+    // Digits and punctuation filled into placeholders are rasterized as well.
+    assert!(font.glyphs.contains_key(&'7') && font.glyphs.contains_key(&'%'));
+    assert!(!font.glyphs.contains_key(&'H'));
+    drop(font);
+    assert!(db.category_bytes(AssetCategory::TTF) > 0);
+
+    fs.insert(
+        "localization.json",
+        r#"{ "default_locale": "en", "locales": [ { "locale": "en", "strings": { "quit": "Halt" } } ] }"#,
+    );
+    let changed = db.poll_changes().unwrap();
+    assert!(changed.contains(&AssetKey::new(AssetCategory::TTF, "ui")));
+    assert_eq!(db.category_bytes(AssetCategory::TTF), 0);
+    assert!(db.fetch_ttf("ui").unwrap().glyphs.contains_key(&'H'));
+}
+
+#[test]
+fn test_custom_categories() {
+    struct LootTable {
+        rolls: u32,
+        text: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct LootFields {
+        rolls: u32,
+    }
+
+    struct LootLoader;
+
+    impl AssetLoader for LootLoader {
+        type Asset = LootTable;
+
+        fn load(&self, entry: &CustomJSONEntry, fs: &dyn FileSystem) -> Result<LootTable, Error> {
+            let fields: LootFields = entry.data()?;
+            let text = match &entry.path {
+                Some(path) => fs.read_to_string(path)?,
+                None => String::new(),
+            };
+            Ok(LootTable { rolls: fields.rolls, text })
+        }
+
+        fn byte_size(&self, asset: &LootTable) -> usize {
+            asset.text.len()
+        }
+    }
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "categories": { "loot": "loot.json" } }"#);
+    fs.insert(
+        "loot.json",
+        r#"{ "entries": [
+            { "name": "chest", "path": "chest.txt", "rolls": 3, "tags": ["rare"] },
+            { "name": "barrel", "rolls": 1,
+              "depends_on": [ { "category": "Custom", "name": "loot/chest" } ] },
+            { "name": "broken", "path": "missing.txt", "rolls": 1 }
+        ] }"#,
+    );
+    fs.insert("chest.txt", "gold");
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    assert_eq!(db.custom_categories(), ["loot"]);
+    assert_eq!(db.custom_names("loot"), ["barrel", "broken", "chest"]);
+    assert!(db.fetch_custom::<LootTable>("loot", "chest").is_err());
+
+    db.register_loader("loot", LootLoader);
+    let chest = db.fetch_custom::<LootTable>("loot", "chest").unwrap();
+    assert_eq!((chest.rolls, chest.text.as_str()), (3, "gold"));
+    assert_eq!(db.custom_entry("loot", "chest").unwrap().byte_size(), 4);
+    assert_eq!(db.category_bytes(AssetCategory::Custom), 4);
+    let barrel = db.fetch_custom::<LootTable>("loot", "barrel").unwrap();
+    assert_eq!(barrel.rolls, 1);
+    assert!(db.fetch_custom::<String>("loot", "chest").is_err());
+    assert!(db.fetch_custom::<LootTable>("loot", "broken").is_err());
+    assert!(db.fetch_custom::<LootTable>("loot", "missing").is_err());
+
+    // Custom entries share lookup, queries, ids and dependencies with built-in ones.
+    let key = AssetKey::custom("loot", "chest");
+    assert_eq!(chest.key(), &key);
+    assert!(db.contains(AssetCategory::Custom, "loot/chest"));
+    assert_eq!(db.find_by_tag("rare"), vec![key.clone()]);
+    assert_eq!(db.find_by_name("loot/b*").len(), 2);
+    assert!(db.fetch_custom_by_id::<LootTable>(key.id()).unwrap().ptr_eq(&chest));
+    assert_eq!(db.loaded_dependents(AssetCategory::Custom, "loot/chest"), [AssetKey::custom("loot", "barrel")]);
+    let fetched = db.fetch_with_dependencies(AssetCategory::Custom, "loot/barrel").unwrap();
+    let keys: Vec<&AssetKey> = fetched.iter().map(|a| a.key()).collect();
+    assert_eq!(keys, [&AssetKey::custom("loot", "barrel"), &key]);
+    match fetched[1].clone() {
+        AnyAsset::Custom(asset) => assert!(asset.downcast::<LootTable>().ok().unwrap().ptr_eq(&chest)),
+        _ => panic!("expected a custom asset"),
+    }
+    drop(fetched);
+    drop(barrel);
+
+    assert!(db.unload_custom("loot", "chest").is_err());
+    db.unload_custom("loot", "barrel").unwrap();
+    db.unload_custom("loot", "chest").unwrap();
+    assert!(!db.custom_entry("loot", "chest").unwrap().is_loaded());
+    assert_eq!(db.category_bytes(AssetCategory::Custom), 0);
+    assert!(db.fetch_custom::<LootTable>("loot", "chest").is_ok());
+    assert!(db.poll_changes().unwrap().is_empty());
+    assert!(db.custom_entry("loot", "chest").unwrap().is_loaded());
+
+    // Source files of loaded entries are watched like those of built-in entries.
+    fs.insert("chest.txt", "silver");
+    assert_eq!(db.poll_changes().unwrap(), vec![key.clone()]);
+    assert_eq!(db.fetch_custom::<LootTable>("loot", "chest").unwrap().text, "silver");
+
+    drop(chest);
+    db.set_memory_budget(MemoryBudget::unlimited().with_category(AssetCategory::Custom, 0));
+    assert!(!db.custom_entry("loot", "chest").unwrap().is_loaded());
+    assert_eq!(db.category_bytes(AssetCategory::Custom), 0);
+
+    let report = db.validate();
+    assert!(report.issues.iter().any(|i| i.entry.as_deref() == Some("broken")));
+}
+
+#[test]
+fn test_audio() {
+    fn wav(channels: u16, frames: i16) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        for frame in 0..frames {
+            for _ in 0..channels {
+                writer.write_sample(frame * 1024).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert("database.json", r#"{ "audio_cfg": "audio.json" }"#);
+    fs.insert(
+        "audio.json",
+        r#"{ "audio": [
+            { "name": "jump", "path": "jump.wav" },
+            { "name": "music", "path": "music.wav", "streaming": true, "loop_points": { "start": 2, "end": 6 } },
+            { "name": "broken", "path": "broken.ogg", "loop_points": { "start": 4, "end": 2 } },
+            { "name": "silence", "path": "silence.ogg" },
+            { "name": "ambience", "path": "silence.ogg", "streaming": true }
+        ] }"#,
+    );
+    fs.insert("jump.wav", wav(1, 4));
+    fs.insert("music.wav", wav(2, 8));
+    fs.insert("broken.ogg", "not vorbis");
+    fs.insert("silence.ogg", include_bytes!("../../sample_database/audio/silence.ogg").to_vec());
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    let jump = db.fetch_audio("jump").unwrap();
+    assert!(!jump.is_streaming());
+    assert_eq!(jump.info, AudioInfo { sample_rate: 22050, channels: 1, frames: Some(4) });
+    assert_eq!(jump.samples().unwrap(), [0.0, 1024.0 / 32768.0, 2048.0 / 32768.0, 3072.0 / 32768.0]);
+    assert_eq!(jump.byte_size(), 16);
+
+    let music = db.fetch_audio("music").unwrap();
+    assert!(music.is_streaming());
+    assert!(music.samples().is_none());
+    assert_eq!(music.loop_points, Some(LoopPoints { start: 2, end: Some(6) }));
+    let mut stream = music.stream().unwrap();
+    assert_eq!(stream.info().channels, 2);
+    assert_eq!(stream.read(3).unwrap().len(), 6);
+    assert_eq!(stream.read(10).unwrap().len(), 10);
+    assert!(stream.read(1).unwrap().is_empty());
+
+    // Streaming clips keep no samples and read their file on every stream.
+    assert_eq!(music.byte_size(), 0);
+    fs.insert("music.wav", wav(2, 5));
+    assert_eq!(music.stream().unwrap().read(100).unwrap().len(), 10);
+    fs.insert("music.wav", wav(2, 8));
+
+    let silence = db.fetch_audio("silence").unwrap();
+    assert_eq!(silence.info, AudioInfo { sample_rate: 44100, channels: 2, frames: Some(512) });
+    assert_eq!(silence.samples().unwrap().len(), 1024);
+    let ambience = db.fetch_audio("ambience").unwrap();
+    assert_eq!(ambience.info, AudioInfo { sample_rate: 44100, channels: 2, frames: None });
+    let mut stream = ambience.stream().unwrap();
+    let mut frames = 0;
+    loop {
+        let chunk = stream.read(100).unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        frames += chunk.len() / 2;
+    }
+    assert_eq!(frames, 512);
+
+    assert!(db.fetch_audio("broken").is_err());
+    assert!(db.fetch_audio("missing").is_err());
+
+    let report = db.validate();
+    assert_eq!(report.of_kind(IssueKind::UnreadableFile).count(), 1);
+    assert_eq!(report.of_kind(IssueKind::InvalidLoopPoints).count(), 0);
+    fs.insert("broken.ogg", wav(1, 4));
+    fs.insert("music.wav", wav(2, 4));
+    let report = db.validate();
+    assert_eq!(report.of_kind(IssueKind::InvalidLoopPoints).count(), 1);
+}
+
+#[test]
+fn test_load_stats() {
+    let fs = Arc::new(MemoryFs::new());
+    fs.insert(
+        "database.json",
+        r#"{ "image_cfg": "images.json", "atlas_cfg": "atlases.json", "ttf_cfg": "ttf.json",
+             "particle_cfg": "particles.json", "audio_cfg": "audio.json" }"#,
+    );
+    fs.insert(
+        "images.json",
+        r#"{ "images": [ { "name": "big", "path": "big.png" }, { "name": "small", "path": "small.png" } ] }"#,
+    );
+    fs.insert("atlases.json", r#"{ "atlases": [ { "name": "sheet", "path": "small.png" } ] }"#);
+    fs.insert("ttf.json", r#"{ "fonts": [ { "name": "ui", "path": "basic.ttf", "size": 12.0 } ] }"#);
+    fs.insert(
+        "particles.json",
+        r#"{ "particles": [ { "name": "sparks", "id": 1, "image_path": "small.png", "animations": [] } ] }"#,
+    );
+    fs.insert("audio.json", r#"{ "audio": [ { "name": "silence", "path": "silence.ogg" } ] }"#);
+    fs.insert("big.png", png_bytes(16, 16));
+    fs.insert("small.png", png_bytes(2, 2));
+    fs.insert("basic.ttf", include_bytes!("../../sample_database/fonts/basic.ttf").to_vec());
+    fs.insert("silence.ogg", include_bytes!("../../sample_database/audio/silence.ogg").to_vec());
+
+    let mut db = Database::with_filesystem(fs.clone()).unwrap();
+    assert!(db.load_stats().entries.is_empty());
+
+    let big = db.fetch_image("big").unwrap();
+    let _sheet = db.fetch_atlas("sheet").unwrap();
+    let _ui = db.fetch_ttf("ui").unwrap();
+    let sparks = db.fetch_particle_system("sparks").unwrap();
+    let silence = db.fetch_audio("silence").unwrap();
+    let handle = db.request_image("small").unwrap();
+    handle.wait();
+    assert!(handle.decode_time().is_some());
+    db.poll_loads();
+
+    let stats = db.entry_load_stats(&AssetKey::new(AssetCategory::Image, "big")).unwrap();
+    assert_eq!(stats.source_bytes, png_bytes(16, 16).len() as u64);
+    assert_eq!(stats.resident_bytes, 16 * 16 * 4);
+    let small = db.entry_load_stats(&AssetKey::new(AssetCategory::Image, "small")).unwrap();
+    assert_eq!(small.resident_bytes, 2 * 2 * 4);
+    assert!(db.entry_load_stats(&AssetKey::new(AssetCategory::Geometry, "none")).is_none());
+    let particle = db.entry_load_stats(&AssetKey::new(AssetCategory::Particle, "sparks")).unwrap();
+    assert_eq!((particle.source_bytes, particle.resident_bytes), (png_bytes(2, 2).len() as u64, sparks.byte_size()));
+    let audio = db.entry_load_stats(&AssetKey::new(AssetCategory::Audio, "silence")).unwrap();
+    assert_eq!((audio.source_bytes, audio.resident_bytes), (204, silence.byte_size()));
+
+    let mut report = db.load_stats();
+    let names: Vec<&str> = report.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["big", "small", "sheet", "ui", "sparks", "silence"]);
+    let images = &report.categories[0];
+    assert_eq!((images.category, images.measured, images.loaded), (AssetCategory::Image, 2, 2));
+    assert_eq!(images.resident_bytes, 16 * 16 * 4 + 2 * 2 * 4);
+
+    report.sort_by(StatsSort::SourceBytes);
+    assert_eq!(report.entries[0].name, "ui");
+    report.sort_by(StatsSort::ResidentBytes);
+    assert_eq!(report.categories[0].category, AssetCategory::TTF);
+
+    // Stats outlive the loaded data.
+    drop(big);
+    db.unload(AssetCategory::Image, "big").unwrap();
+    let report = db.load_stats();
+    assert!(!report.entries[0].loaded);
+    assert_eq!(report.categories[0].loaded, 1);
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["entries"][0]["name"], "big");
+    assert_eq!(json["categories"][0]["category"], "Image");
+    assert!(json["entries"][0]["decode_ms"].is_number());
 }
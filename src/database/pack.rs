@@ -0,0 +1,341 @@
+use super::error::*;
+use super::vfs::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Layout (all integers little endian):
+//   magic "RMPK" | version u32 | entry count u32
+//   per entry: name length u32 | name | offset u64 | length u64 | raw length u64 | compression u8
+//   blobs, at the absolute offsets given in the index
+const PACK_MAGIC: &[u8; 4] = b"RMPK";
+const PACK_VERSION: u32 = 1;
+const PACK_HEADER_SIZE: u64 = 12;
+// Name length, offset, length, raw length and compression, without the name.
+const PACK_ENTRY_SIZE: u64 = 4 + 8 * 3 + 1;
+// An LZ4 block expands to at most 255 times its size.
+const LZ4_MAX_RATIO: u64 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => Ok(lz4_flex::block::compress(data)),
+            Compression::Zstd => Ok(zstd::bulk::compress(data, 0)?),
+        }
+    }
+
+    /// Decompresses `data` into `raw_length` bytes. LZ4 allocates `raw_length` up
+    /// front, which `Pack::open` bounds; Zstd output grows as it is decoded and
+    /// stops past `raw_length`.
+    fn decompress(self, data: Vec<u8>, raw_length: u64) -> Result<Vec<u8>, Error> {
+        let raw = match self {
+            Compression::None => data,
+            Compression::Lz4 => lz4_flex::block::decompress(&data, raw_length as usize)
+                .map_err(|e| Error::from(e.to_string()))?,
+            Compression::Zstd => {
+                let mut raw = Vec::new();
+                zstd::stream::read::Decoder::new(data.as_slice())?
+                    .take(raw_length.saturating_add(1))
+                    .read_to_end(&mut raw)?;
+                raw
+            }
+        };
+
+        if raw.len() as u64 != raw_length {
+            return Err(Error::from("decompressed size does not match the pack index"));
+        }
+        Ok(raw)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+    pub raw_length: u64,
+    pub compression: Compression,
+}
+
+/// Strips the leading `./` that configs commonly put in front of relative paths.
+pub fn normalize_pack_name(name: &str) -> &str {
+    let mut name = name;
+    while let Some(rest) = name.strip_prefix("./") {
+        name = rest;
+    }
+    name
+}
+
+fn read_u32(file: &mut File) -> Result<u32, Error> {
+    let mut buf = [0u8; 4];
+    file.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(file: &mut File) -> Result<u64, Error> {
+    let mut buf = [0u8; 8];
+    file.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn bad_pack(path: &str, reason: &str) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: "[UNKNOWN]".to_string(),
+        path: path.to_string(),
+        cause: reason.to_string(),
+    })
+}
+
+/// A database packed into a single file. Only the index is kept in memory; blobs
+/// are read on demand.
+pub struct Pack {
+    path: String,
+    file: Mutex<File>,
+    index: HashMap<String, PackEntry>,
+    /// Zip archives stored in the pack, opened on first read through them.
+    archives: Mutex<HashMap<String, Arc<ZipFs>>>,
+}
+
+impl Pack {
+    /// Opens a pack and reads its index. Every size in the index is checked against
+    /// the file length before anything is allocated for it, and LZ4 raw lengths
+    /// against the most an LZ4 block can expand to.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != PACK_MAGIC {
+            return Err(bad_pack(path, "not a database pack"));
+        }
+        if read_u32(&mut file)? != PACK_VERSION {
+            return Err(bad_pack(path, "unsupported pack version"));
+        }
+
+        let count = read_u32(&mut file)?;
+        if count as u64 * PACK_ENTRY_SIZE > file_len - PACK_HEADER_SIZE {
+            return Err(bad_pack(path, "corrupt index"));
+        }
+
+        let mut index = HashMap::new();
+        for _ in 0..count {
+            let name_len = read_u32(&mut file)? as u64;
+            if name_len > file_len - file.stream_position()? {
+                return Err(bad_pack(path, "corrupt index"));
+            }
+            let mut name = vec![0u8; name_len as usize];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| bad_pack(path, "corrupt index"))?;
+            let offset = read_u64(&mut file)?;
+            let length = read_u64(&mut file)?;
+            let raw_length = read_u64(&mut file)?;
+            let mut compression = [0u8; 1];
+            file.read_exact(&mut compression)?;
+            let compression = Compression::from_u8(compression[0])
+                .ok_or_else(|| bad_pack(path, "unknown compression"))?;
+            let in_file = offset
+                .checked_add(length)
+                .map(|end| end <= file_len)
+                .unwrap_or(false);
+            let raw_fits = match compression {
+                Compression::None => raw_length == length,
+                Compression::Lz4 => raw_length <= length.saturating_mul(LZ4_MAX_RATIO),
+                Compression::Zstd => true,
+            };
+            if !in_file || !raw_fits {
+                return Err(bad_pack(path, "corrupt index"));
+            }
+
+            index.insert(
+                name.clone(),
+                PackEntry {
+                    name,
+                    offset,
+                    length,
+                    raw_length,
+                    compression,
+                },
+            );
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            file: Mutex::new(file),
+            index,
+            archives: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &PackEntry> {
+        self.index.values()
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&PackEntry> {
+        self.index.get(normalize_pack_name(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(normalize_pack_name(name))
+    }
+
+    /// Reads and decompresses the blob stored under `name`.
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let entry = self
+            .index
+            .get(normalize_pack_name(name))
+            .ok_or_else(|| not_found(&self.path, name))?;
+
+        let mut data = vec![0u8; entry.length as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(entry.offset))?;
+            file.read_exact(&mut data)?;
+        }
+
+        entry.compression.decompress(data, entry.raw_length)
+    }
+
+    /// Opens the zip archive stored under `name`.
+    pub(crate) fn archive(&self, name: &str) -> Result<Arc<ZipFs>, Error> {
+        let name = normalize_pack_name(name);
+        if let Some(zip) = self.archives.lock().unwrap().get(name) {
+            return Ok(zip.clone());
+        }
+
+        let zip = Arc::new(ZipFs::from_bytes(&format!("{}/{}", self.path, name), self.read(name)?)?);
+        self.archives.lock().unwrap().insert(name.to_string(), zip.clone());
+        Ok(zip)
+    }
+}
+
+/// Collects blobs and writes them out as a pack.
+#[derive(Default)]
+pub struct PackBuilder {
+    blobs: Vec<(String, Vec<u8>, Compression)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, data: Vec<u8>, compression: Compression) -> &mut Self {
+        self.blobs
+            .push((normalize_pack_name(name).to_string(), data, compression));
+        self
+    }
+
+    /// Adds every file under `dir`, named by its `/`-separated path relative to `dir`.
+    /// `compression` picks the compression for each file from its name.
+    pub fn add_directory(
+        &mut self,
+        dir: &str,
+        compression: &dyn Fn(&str) -> Compression,
+    ) -> Result<&mut Self, Error> {
+        let mut stack = vec![Path::new(dir).to_path_buf()];
+        while let Some(current) = stack.pop() {
+            for item in std::fs::read_dir(&current)? {
+                let path = item?.path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+
+                let relative = path
+                    .strip_prefix(dir)
+                    .map_err(|_| bad_pack(dir, "file outside of directory"))?;
+                let name = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let data = std::fs::read(&path)?;
+                let chosen = compression(&name);
+                self.add(&name, data, chosen);
+            }
+        }
+
+        Ok(self)
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), Error> {
+        let mut compressed = Vec::with_capacity(self.blobs.len());
+        for (name, data, compression) in &self.blobs {
+            compressed.push((name, compression.compress(data)?, data.len(), *compression));
+        }
+
+        let index_size: usize = compressed
+            .iter()
+            .map(|(name, ..)| 4 + name.len() + 8 * 3 + 1)
+            .sum();
+        let mut offset = (PACK_MAGIC.len() + 4 + 4 + index_size) as u64;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(PACK_MAGIC);
+        out.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        for (name, data, raw_length, compression) in &compressed {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            out.extend_from_slice(&(*raw_length as u64).to_le_bytes());
+            out.push(compression.to_u8());
+            offset += data.len() as u64;
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(&out)?;
+        for (_, data, ..) in &compressed {
+            file.write_all(data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Packs the database directory `dir` into `out`. Files that are already compressed
+/// (PNG, JPEG, zip) are stored as-is; everything else uses `compression`.
+pub fn build_pack(dir: &str, out: &str, compression: Compression) -> Result<(), Error> {
+    let choose = |name: &str| {
+        let ext = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        match ext.as_str() {
+            "png" | "jpg" | "jpeg" | "zip" => Compression::None,
+            _ => compression,
+        }
+    };
+
+    PackBuilder::new().add_directory(dir, &choose)?.write(out)
+}
@@ -0,0 +1,63 @@
+use super::error::*;
+use super::handle::*;
+use super::images::*;
+use super::json::*;
+use super::load_funcs::*;
+use super::stats::*;
+use super::vfs::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A particle definition with its sheet image loaded.
+pub struct ParticleSystem {
+    pub name: String,
+    pub id: u32,
+    pub image: ImageLoadInfo<u8>,
+    pub animations: Vec<ParticleAnimationJSON>,
+}
+
+impl ParticleSystem {
+    pub fn animation(&self, name: &str) -> Option<&ParticleAnimationJSON> {
+        self.animations.iter().find(|a| a.name == name)
+    }
+
+    pub fn animation_by_id(&self, id: u32) -> Option<&ParticleAnimationJSON> {
+        self.animations.iter().find(|a| a.id == id)
+    }
+
+    pub fn byte_size(&self) -> usize {
+        let frames: usize = self.animations.iter().map(|a| a.sprites.len()).sum();
+        self.image.byte_size() + frames * std::mem::size_of::<dashi::Rect2D>()
+    }
+}
+
+pub fn load_entry_particle(cfg: &ParticleJSONEntry, fs: &dyn FileSystem) -> Result<ParticleSystem, Error> {
+    Ok(ParticleSystem {
+        name: cfg.name.clone(),
+        id: cfg.id,
+        image: load_entry_image(&cfg.name, fs, &cfg.image_path)?,
+        animations: cfg.animations.clone(),
+    })
+}
+
+pub struct ParticleEntry {
+    pub cfg: ParticleJSONEntry,
+    pub layer: usize,
+    pub loaded: Option<Arc<ParticleSystem>>,
+    /// See `DatabaseEntry::load_stats`.
+    pub stats: Option<LoadStats>,
+}
+
+impl ParticleEntry {
+    pub fn load(&mut self, fs: &dyn FileSystem) -> Result<(), Error> {
+        let (data, time) = timed(|| load_entry_particle(&self.cfg, fs))?;
+        self.set_loaded(data, time, fs);
+        Ok(())
+    }
+}
+
+impl_entry!(ParticleEntry, ParticleJSONEntry, ParticleSystem, AssetCategory::Particle, path: image_path);
+
+pub fn parse_particles(info: ParticleJSON, layer: usize) -> Vec<ParticleEntry> {
+    parse_entries(info.particles, layer)
+}
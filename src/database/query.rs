@@ -0,0 +1,183 @@
+use super::*;
+
+/// Matches `name` against a glob `pattern`. `*` matches any run of characters,
+/// `?` matches exactly one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name position it currently covers up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Filters over database entries. Filters that are not set match every entry.
+#[derive(Debug, Clone, Default)]
+pub struct AssetQuery {
+    pub category: Option<AssetCategory>,
+    /// Tags an entry must all have.
+    pub tags: Vec<String>,
+    /// Glob the entry name must match, see `glob_match`.
+    pub name: Option<String>,
+}
+
+impl AssetQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn category(mut self, category: AssetCategory) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn name(mut self, glob: &str) -> Self {
+        self.name = Some(glob.to_string());
+        self
+    }
+
+    /// Whether the entry `key` with `tags` passes every filter. Custom entries are
+    /// matched by their `<category>/<name>` key name.
+    pub fn matches(&self, key: &AssetKey, tags: &[String]) -> bool {
+        self.category.map(|c| c == key.category).unwrap_or(true)
+            && self.tags.iter().all(|t| tags.contains(t))
+            && self
+                .name
+                .as_ref()
+                .map(|glob| glob_match(glob, &key.name))
+                .unwrap_or(true)
+    }
+}
+
+fn collect_matches<E: DatabaseEntry>(
+    entries: &EntryMap<E>,
+    category: AssetCategory,
+    query: &AssetQuery,
+    found: &mut Vec<AssetKey>,
+) {
+    if query.category.map(|c| c != category).unwrap_or(false) {
+        return;
+    }
+
+    for entry in entries.values() {
+        let key = AssetKey::new(category, entry.name());
+        if query.matches(&key, entry.cfg().tags()) {
+            found.push(key);
+        }
+    }
+}
+
+/// Shaders have no config, so they have no tags.
+fn collect_shader_matches(shaders: &HashMap<String, ShaderEntry>, query: &AssetQuery, found: &mut Vec<AssetKey>) {
+    for name in shaders.keys() {
+        let key = AssetKey::new(AssetCategory::Shader, name);
+        if query.matches(&key, &[]) {
+            found.push(key);
+        }
+    }
+}
+
+impl Database {
+    /// Names of every entry in `category`, in no particular order.
+    pub fn names(&self, category: AssetCategory) -> Box<dyn Iterator<Item = &str> + '_> {
+        match category {
+            AssetCategory::Image => Box::new(self.images.names()),
+            AssetCategory::Atlas => Box::new(self.atlases.names()),
+            AssetCategory::Geometry => Box::new(self.geometry.names()),
+            AssetCategory::TTF => Box::new(self.ttfs.names()),
+            AssetCategory::Particle => Box::new(self.particles.names()),
+            AssetCategory::Audio => Box::new(self.audio.names()),
+            AssetCategory::Shader => Box::new(self.shader_names()),
+            AssetCategory::Custom => Box::new(self.custom.names()),
+        }
+    }
+
+    pub fn image_configs(&self) -> impl Iterator<Item = &ImageJSONEntry> {
+        self.images.values().map(|e| &e.cfg)
+    }
+
+    pub fn atlas_configs(&self) -> impl Iterator<Item = &AtlasJSONEntry> {
+        self.atlases.values().map(|e| &e.cfg)
+    }
+
+    pub fn ttf_configs(&self) -> impl Iterator<Item = &TTFJSONEntry> {
+        self.ttfs.values().map(|e| &e.cfg)
+    }
+
+    pub fn geometry_configs(&self) -> impl Iterator<Item = &GeometryJSONEntry> {
+        self.geometry.values().map(|e| &e.cfg)
+    }
+
+    pub fn particle_configs(&self) -> impl Iterator<Item = &ParticleJSONEntry> {
+        self.particles.values().map(|e| &e.cfg)
+    }
+
+    pub fn audio_configs(&self) -> impl Iterator<Item = &AudioJSONEntry> {
+        self.audio.values().map(|e| &e.cfg)
+    }
+
+    pub fn contains(&self, category: AssetCategory, name: &str) -> bool {
+        match category {
+            AssetCategory::Image => self.images.contains_key(name),
+            AssetCategory::Atlas => self.atlases.contains_key(name),
+            AssetCategory::Geometry => self.geometry.contains_key(name),
+            AssetCategory::TTF => self.ttfs.contains_key(name),
+            AssetCategory::Particle => self.particles.contains_key(name),
+            AssetCategory::Audio => self.audio.contains_key(name),
+            AssetCategory::Shader => self.shaders.contains_key(name),
+            AssetCategory::Custom => self.custom.contains_key(name),
+        }
+    }
+
+    /// Every entry matching `query`, sorted by category and name.
+    pub fn query(&self, query: &AssetQuery) -> Vec<AssetKey> {
+        let mut found = Vec::new();
+        collect_matches(&self.images, AssetCategory::Image, query, &mut found);
+        collect_matches(&self.atlases, AssetCategory::Atlas, query, &mut found);
+        collect_matches(&self.geometry, AssetCategory::Geometry, query, &mut found);
+        collect_matches(&self.ttfs, AssetCategory::TTF, query, &mut found);
+        collect_matches(&self.particles, AssetCategory::Particle, query, &mut found);
+        collect_matches(&self.audio, AssetCategory::Audio, query, &mut found);
+        collect_shader_matches(&self.shaders, query, &mut found);
+        collect_matches(&self.custom, AssetCategory::Custom, query, &mut found);
+        found.sort();
+        found
+    }
+
+    /// Every entry tagged `tag`.
+    pub fn find_by_tag(&self, tag: &str) -> Vec<AssetKey> {
+        self.query(&AssetQuery::new().tag(tag))
+    }
+
+    /// Every entry whose name matches `glob`.
+    pub fn find_by_name(&self, glob: &str) -> Vec<AssetKey> {
+        self.query(&AssetQuery::new().name(glob))
+    }
+
+    /// Every entry of `category`.
+    pub fn find_in_category(&self, category: AssetCategory) -> Vec<AssetKey> {
+        self.query(&AssetQuery::new().category(category))
+    }
+}
@@ -0,0 +1,101 @@
+use super::validate::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Name in `execution_order` that stands for presenting the final image. It is
+/// not a node.
+pub const OUTPUT_NODE: &str = "OUTPUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AttachmentType {
+    Color,
+    Depth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AttachmentJSON {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: AttachmentType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SubpassJSON {
+    pub name: String,
+    pub attachments: Vec<AttachmentJSON>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RenderPassJSON {
+    pub size: [u32; 2],
+    pub subpasses: Vec<SubpassJSON>,
+}
+
+/// The shader a node runs, named as in the shader library.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineJSON {
+    Graphics(String),
+    Compute(String),
+}
+
+impl PipelineJSON {
+    pub fn shader(&self) -> &str {
+        match self {
+            PipelineJSON::Graphics(name) => name,
+            PipelineJSON::Compute(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RenderNodeJSON {
+    pub name: String,
+    pub pipeline: PipelineJSON,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subpass: Option<String>,
+    /// Names from the graph's `render_mask` table; only meshes with one of these
+    /// masks are drawn by the node.
+    #[serde(default)]
+    pub render_masks: Vec<String>,
+}
+
+/// A render graph description, the file `DatabaseJSON::render_graph_path` points at.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RenderGraph {
+    #[serde(default)]
+    pub version: u32,
+    pub render_pass: RenderPassJSON,
+    pub render_mask: BTreeMap<String, u32>,
+    pub nodes: Vec<RenderNodeJSON>,
+    /// Node names in the order they run, usually ending in `OUTPUT_NODE`.
+    pub execution_order: Vec<String>,
+}
+
+impl RenderGraph {
+    pub fn node(&self, name: &str) -> Option<&RenderNodeJSON> {
+        self.nodes.iter().find(|n| n.name == name)
+    }
+
+    pub fn subpass(&self, name: &str) -> Option<&SubpassJSON> {
+        self.render_pass.subpasses.iter().find(|s| s.name == name)
+    }
+
+    pub fn render_mask(&self, name: &str) -> Option<u32> {
+        self.render_mask.get(name).copied()
+    }
+
+    /// The nodes of `execution_order`, in order, without the output sentinel.
+    pub fn ordered_nodes(&self) -> impl Iterator<Item = &RenderNodeJSON> {
+        self.execution_order.iter().filter_map(|name| self.node(name))
+    }
+
+    /// Checks the references between subpasses, render masks and nodes. `config`
+    /// names the graph's file in the reported issues.
+    pub fn validate(&self, config: &str) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        validate_render_graph(config, self, &mut report);
+        report
+    }
+}
@@ -0,0 +1,199 @@
+use super::error::*;
+use super::format::DATABASE_CONFIGS;
+use super::json::*;
+use super::render_graph::*;
+use super::vfs::*;
+use crate::canvas::CanvasCreateInfo;
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde_json::json;
+
+// Schema stand-ins for the dashi types the configs embed, which don't implement
+// `JsonSchema` themselves. Keep them in sync with dashi's serde layout;
+// `test_config_schemas` reads every variant and field back into the dashi types
+// and stops compiling when dashi adds a variant.
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "Rect2D")]
+pub struct Rect2DSchema {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "FRect2D")]
+pub struct FRect2DSchema {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "Viewport")]
+pub struct ViewportSchema {
+    area: FRect2DSchema,
+    scissor: Rect2DSchema,
+    min_depth: f32,
+    max_depth: f32,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "Format")]
+pub enum FormatSchema {
+    RGB8,
+    BGRA8,
+    BGRA8Unorm,
+    RGBA8,
+    RGBA32F,
+    D24S8,
+    R8Sint,
+    R8Uint,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "SampleCount")]
+pub enum SampleCountSchema {
+    S1,
+    S2,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "LoadOp")]
+pub enum LoadOpSchema {
+    Load,
+    Clear,
+    DontCare,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename = "StoreOp")]
+pub enum StoreOpSchema {
+    Store,
+    DontCare,
+}
+
+/// A JSON Schema document and the config files it describes.
+pub struct ConfigSchema {
+    /// File name the schema is exported under.
+    pub file_name: &'static str,
+    /// Paths of the config files the schema applies to, as declared by a root
+    /// config. Configs still in an older layout don't match the schema until
+    /// `Database::upgrade_configs` rewrites them.
+    pub file_match: fn(&DatabaseJSON) -> Vec<String>,
+    pub schema: RootSchema,
+}
+
+fn declared(path: &Option<String>) -> Vec<String> {
+    path.iter().cloned().collect()
+}
+
+/// Schemas of every hand-edited config format. Material and canvas files aren't
+/// declared by the root config, so they are matched by their usual names.
+pub fn config_schemas() -> Vec<ConfigSchema> {
+    vec![
+        ConfigSchema {
+            file_name: "database.schema.json",
+            file_match: |_| vec![DATABASE_CONFIGS[0].to_string()],
+            schema: schema_for!(DatabaseJSON),
+        },
+        ConfigSchema {
+            file_name: "images.schema.json",
+            file_match: |info| declared(&info.image_cfg),
+            schema: schema_for!(ImageJSON),
+        },
+        ConfigSchema {
+            file_name: "atlases.schema.json",
+            file_match: |info| declared(&info.atlas_cfg),
+            schema: schema_for!(AtlasJSON),
+        },
+        ConfigSchema {
+            file_name: "ttf.schema.json",
+            file_match: |info| declared(&info.ttf_cfg),
+            schema: schema_for!(TTFJSON),
+        },
+        ConfigSchema {
+            file_name: "geometry.schema.json",
+            file_match: |info| declared(&info.geometry_cfg),
+            schema: schema_for!(GeometryJSON),
+        },
+        ConfigSchema {
+            file_name: "materials.schema.json",
+            file_match: |_| vec!["materials.json".to_string()],
+            schema: schema_for!(MaterialJSON),
+        },
+        ConfigSchema {
+            file_name: "particle.schema.json",
+            file_match: |info| declared(&info.particle_cfg),
+            schema: schema_for!(ParticleJSON),
+        },
+        ConfigSchema {
+            file_name: "audio.schema.json",
+            file_match: |info| declared(&info.audio_cfg),
+            schema: schema_for!(AudioJSON),
+        },
+        ConfigSchema {
+            file_name: "localization.schema.json",
+            file_match: |info| declared(&info.localization_cfg),
+            schema: schema_for!(LocalizationJSON),
+        },
+        ConfigSchema {
+            file_name: "render_graph.schema.json",
+            file_match: |info| declared(&info.render_graph_path),
+            schema: schema_for!(RenderGraph),
+        },
+        ConfigSchema {
+            file_name: "canvas.schema.json",
+            file_match: |_| vec!["canvas.json".to_string()],
+            schema: schema_for!(CanvasCreateInfo),
+        },
+    ]
+}
+
+fn schema_path(dir: &str, file_name: &str) -> String {
+    let dir = dir.trim_end_matches('/');
+    if dir.is_empty() {
+        file_name.to_string()
+    } else {
+        format!("{}/{}", dir, file_name)
+    }
+}
+
+/// Writes every schema into `dir` of `fs`. Returns the written paths.
+pub fn export_schemas(fs: &dyn FileSystem, dir: &str) -> Result<Vec<String>, Error> {
+    let mut written = Vec::new();
+    for schema in config_schemas() {
+        let path = schema_path(dir, schema.file_name);
+        let mut text = serde_json::to_string_pretty(&schema.schema)?;
+        text.push('\n');
+        fs.write(&path, text.as_bytes())?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The `json.schemas` setting that points VS Code at schemas exported to `dir`,
+/// for merging into `.vscode/settings.json`. `dir` is relative to the workspace;
+/// each schema applies to the configs that `info` declares.
+pub fn vscode_schema_settings(dir: &str, info: &DatabaseJSON) -> serde_json::Value {
+    let schemas: Vec<serde_json::Value> = config_schemas()
+        .iter()
+        .map(|schema| {
+            let file_match: Vec<String> = (schema.file_match)(info).iter().map(|f| format!("**/{}", f)).collect();
+            json!({
+                "fileMatch": file_match,
+                "url": format!("./{}", schema_path(dir, schema.file_name)),
+            })
+        })
+        .collect();
+    json!({ "json.schemas": schemas })
+}
@@ -0,0 +1,275 @@
+use super::error::*;
+use super::handle::*;
+use super::json::*;
+use super::pack::*;
+use super::vfs::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    /// Stage of a GLSL or SPIR-V file named `<shader>.<extension>[.glsl|.spv]`.
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "vert" => Some(ShaderStage::Vertex),
+            "frag" => Some(ShaderStage::Fragment),
+            "comp" => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+
+    fn to_naga(self) -> naga::ShaderStage {
+        match self {
+            ShaderStage::Vertex => naga::ShaderStage::Vertex,
+            ShaderStage::Fragment => naga::ShaderStage::Fragment,
+            ShaderStage::Compute => naga::ShaderStage::Compute,
+        }
+    }
+
+    fn from_naga(stage: naga::ShaderStage) -> Self {
+        match stage {
+            naga::ShaderStage::Vertex => ShaderStage::Vertex,
+            naga::ShaderStage::Fragment => ShaderStage::Fragment,
+            naga::ShaderStage::Compute => ShaderStage::Compute,
+        }
+    }
+}
+
+/// One stage of a shader, compiled to SPIR-V.
+pub struct ShaderModule {
+    pub stage: ShaderStage,
+    pub entry_point: String,
+    pub spirv: Vec<u32>,
+}
+
+/// Every stage of a named shader, e.g. the vertex and fragment stage of the
+/// `stdrender` pipeline.
+pub struct Shader {
+    pub name: String,
+    pub stages: Vec<ShaderModule>,
+}
+
+impl Shader {
+    pub fn stage(&self, stage: ShaderStage) -> Option<&ShaderModule> {
+        self.stages.iter().find(|s| s.stage == stage)
+    }
+
+    pub fn is_compute(&self) -> bool {
+        self.stage(ShaderStage::Compute).is_some()
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.stages.iter().map(|s| s.spirv.len() * 4).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaderSourceKind {
+    Glsl(ShaderStage),
+    /// WGSL files hold every stage; the entry points tell them apart.
+    Wgsl,
+    SpirV(ShaderStage),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderSource {
+    pub path: String,
+    pub kind: ShaderSourceKind,
+}
+
+/// Splits a path below the shader directory into a shader name and the kind of
+/// source it holds. Files that aren't shaders give `None`.
+///
+/// Recognized names are `<name>.wgsl`, `<name>.<stage>`, `<name>.<stage>.glsl` and
+/// `<name>.<stage>.spv`, with `<stage>` one of `vert`, `frag` or `comp`.
+pub fn parse_shader_path(relative: &str) -> Option<(String, ShaderSourceKind)> {
+    let (rest, extension) = relative.rsplit_once('.')?;
+    if extension == "wgsl" {
+        return Some((rest.to_string(), ShaderSourceKind::Wgsl));
+    }
+    if let Some(stage) = ShaderStage::from_extension(extension) {
+        return Some((rest.to_string(), ShaderSourceKind::Glsl(stage)));
+    }
+
+    let (name, stage) = rest.rsplit_once('.')?;
+    let stage = ShaderStage::from_extension(stage)?;
+    match extension {
+        "glsl" => Some((name.to_string(), ShaderSourceKind::Glsl(stage))),
+        "spv" => Some((name.to_string(), ShaderSourceKind::SpirV(stage))),
+        _ => None,
+    }
+}
+
+pub struct ShaderEntry {
+    pub name: String,
+    pub layer: usize,
+    pub sources: Vec<ShaderSource>,
+    pub loaded: Option<Arc<Shader>>,
+}
+
+impl ShaderEntry {
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.is_some()
+    }
+
+    pub fn unload(&mut self) {
+        self.loaded = None;
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.loaded.as_ref().map(|s| s.byte_size()).unwrap_or(0)
+    }
+
+    pub fn handle_count(&self) -> usize {
+        self.loaded.as_ref().map(|s| Arc::strong_count(s) - 1).unwrap_or(0)
+    }
+
+    /// Whether a dropped handle to the data at `addr` was the last one. See
+    /// `is_released`.
+    pub fn is_released(&self, addr: usize) -> bool {
+        self.loaded.as_ref().map(data_addr) == Some(addr) && self.handle_count() == 0
+    }
+
+    pub fn load(&mut self, fs: &dyn FileSystem) -> Result<(), Error> {
+        let mut stages: Vec<ShaderModule> = Vec::new();
+        for source in &self.sources {
+            for module in load_shader_source(&self.name, fs, source)? {
+                if stages.iter().any(|s| s.stage == module.stage) {
+                    let cause = format!("second {:?} stage", module.stage);
+                    return Err(shader_error(&self.name, &source.path, cause));
+                }
+                stages.push(module);
+            }
+        }
+
+        self.loaded = Some(Arc::new(Shader {
+            name: self.name.clone(),
+            stages,
+        }));
+        Ok(())
+    }
+}
+
+fn shader_error(name: &str, path: &str, cause: impl std::fmt::Display) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: name.to_string(),
+        path: path.to_string(),
+        cause: cause.to_string(),
+    })
+}
+
+/// Validates `module` and writes the entry point `entry_point` of `stage` as SPIR-V.
+fn write_spirv(
+    name: &str,
+    path: &str,
+    module: &naga::Module,
+    stage: ShaderStage,
+    entry_point: &str,
+) -> Result<ShaderModule, Error> {
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(module)
+    .map_err(|e| shader_error(name, path, e))?;
+
+    let pipeline = naga::back::spv::PipelineOptions {
+        shader_stage: stage.to_naga(),
+        entry_point: entry_point.to_string(),
+    };
+    let spirv = naga::back::spv::write_vec(module, &info, &naga::back::spv::Options::default(), Some(&pipeline))
+        .map_err(|e| shader_error(name, path, e))?;
+
+    Ok(ShaderModule {
+        stage,
+        entry_point: entry_point.to_string(),
+        spirv,
+    })
+}
+
+fn spirv_words(name: &str, path: &str, bytes: &[u8]) -> Result<Vec<u32>, Error> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(shader_error(name, path, "SPIR-V size is not a multiple of 4"));
+    }
+
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .collect();
+    if words.first() != Some(&SPIRV_MAGIC) {
+        return Err(shader_error(name, path, "not a SPIR-V binary"));
+    }
+    Ok(words)
+}
+
+/// Loads the stages one source file provides, compiling GLSL and WGSL to SPIR-V.
+pub fn load_shader_source(name: &str, fs: &dyn FileSystem, source: &ShaderSource) -> Result<Vec<ShaderModule>, Error> {
+    let path = &source.path;
+    match source.kind {
+        ShaderSourceKind::SpirV(stage) => Ok(vec![ShaderModule {
+            stage,
+            entry_point: "main".to_string(),
+            spirv: spirv_words(name, path, &fs.read(path)?)?,
+        }]),
+        ShaderSourceKind::Glsl(stage) => {
+            let text = fs.read_to_string(path)?;
+            let options = naga::front::glsl::Options::from(stage.to_naga());
+            let module = naga::front::glsl::Frontend::default()
+                .parse(&options, &text)
+                .map_err(|errors| {
+                    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                    shader_error(name, path, messages.join("; "))
+                })?;
+            Ok(vec![write_spirv(name, path, &module, stage, "main")?])
+        }
+        ShaderSourceKind::Wgsl => {
+            let text = fs.read_to_string(path)?;
+            let module = naga::front::wgsl::parse_str(&text)
+                .map_err(|e| shader_error(name, path, e.emit_to_string(&text)))?;
+            module
+                .entry_points
+                .iter()
+                .map(|ep| write_spirv(name, path, &module, ShaderStage::from_naga(ep.stage), &ep.name))
+                .collect()
+        }
+    }
+}
+
+/// Finds the shaders below the `shader_path` of every layer. A shader in a higher
+/// layer replaces every stage of the same name in lower ones.
+pub fn discover_shaders(layers: &[Arc<dyn FileSystem>], infos: &[DatabaseJSON]) -> HashMap<String, ShaderEntry> {
+    let mut shaders = HashMap::new();
+    for (layer, (fs, info)) in layers.iter().zip(infos).enumerate() {
+        let dir = match &info.shader_path {
+            Some(dir) => normalize_pack_name(dir).trim_end_matches('/'),
+            None => continue,
+        };
+
+        let mut found: HashMap<String, ShaderEntry> = HashMap::new();
+        for path in fs.list(dir) {
+            let relative = path[dir.len()..].trim_start_matches('/');
+            if let Some((name, kind)) = parse_shader_path(relative) {
+                found
+                    .entry(name.clone())
+                    .or_insert_with(|| ShaderEntry {
+                        name,
+                        layer,
+                        sources: Vec::new(),
+                        loaded: None,
+                    })
+                    .sources
+                    .push(ShaderSource { path, kind });
+            }
+        }
+        shaders.extend(found);
+    }
+    shaders
+}
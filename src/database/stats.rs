@@ -0,0 +1,174 @@
+use super::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Measurements taken the last time an entry was loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Time spent reading and decoding the source file.
+    pub decode_time: Duration,
+    /// Size of the source file. Files it refers to, like external glTF buffers,
+    /// are not counted.
+    pub source_bytes: u64,
+    /// Size of the decoded data in memory.
+    pub resident_bytes: usize,
+}
+
+impl LoadStats {
+    pub fn new(fs: &dyn FileSystem, path: &str, decode_time: Duration, resident_bytes: usize) -> Self {
+        Self {
+            decode_time,
+            source_bytes: fs.file_size(path).unwrap_or(0),
+            resident_bytes,
+        }
+    }
+}
+
+/// Runs `load` and measures how long it took.
+pub(crate) fn timed<T>(load: impl FnOnce() -> Result<T, Error>) -> Result<(T, Duration), Error> {
+    let start = Instant::now();
+    let data = load()?;
+    Ok((data, start.elapsed()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryStats {
+    pub category: AssetCategory,
+    pub name: String,
+    /// Whether the entry is still resident. Stats of unloaded entries are those of
+    /// their last load.
+    pub loaded: bool,
+    pub decode_ms: f64,
+    pub source_bytes: u64,
+    pub resident_bytes: usize,
+}
+
+/// Totals over the entries of a category that have been loaded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryStats {
+    pub category: AssetCategory,
+    /// Entries loaded at least once, resident or not. Entries never loaded have
+    /// no stats and are not counted.
+    pub measured: usize,
+    pub loaded: usize,
+    pub decode_ms: f64,
+    pub source_bytes: u64,
+    pub resident_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsSort {
+    /// By category, then name.
+    Name,
+    DecodeTime,
+    SourceBytes,
+    ResidentBytes,
+}
+
+/// Load measurements of every entry that has been loaded, as returned by `Database::load_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatsReport {
+    pub categories: Vec<CategoryStats>,
+    pub entries: Vec<EntryStats>,
+}
+
+impl StatsReport {
+    fn from_entries(entries: Vec<EntryStats>) -> Self {
+        let mut categories: Vec<CategoryStats> = Vec::new();
+        for entry in &entries {
+            let total = match categories.iter_mut().find(|c| c.category == entry.category) {
+                Some(total) => total,
+                None => {
+                    categories.push(CategoryStats {
+                        category: entry.category,
+                        measured: 0,
+                        loaded: 0,
+                        decode_ms: 0.0,
+                        source_bytes: 0,
+                        resident_bytes: 0,
+                    });
+                    categories.last_mut().unwrap()
+                }
+            };
+            total.measured += 1;
+            total.loaded += entry.loaded as usize;
+            total.decode_ms += entry.decode_ms;
+            total.source_bytes += entry.source_bytes;
+            total.resident_bytes += entry.resident_bytes;
+        }
+
+        let mut report = StatsReport { categories, entries };
+        report.sort_by(StatsSort::Name);
+        report
+    }
+
+    /// Sorts both categories and entries by `key`. Measurements sort largest first.
+    pub fn sort_by(&mut self, key: StatsSort) {
+        match key {
+            StatsSort::Name => {
+                self.categories.sort_by_key(|c| c.category);
+                self.entries.sort_by(|a, b| (a.category, &a.name).cmp(&(b.category, &b.name)));
+            }
+            StatsSort::DecodeTime => {
+                self.categories.sort_by(|a, b| b.decode_ms.total_cmp(&a.decode_ms));
+                self.entries.sort_by(|a, b| b.decode_ms.total_cmp(&a.decode_ms));
+            }
+            StatsSort::SourceBytes => {
+                self.categories.sort_by_key(|c| std::cmp::Reverse(c.source_bytes));
+                self.entries.sort_by_key(|e| std::cmp::Reverse(e.source_bytes));
+            }
+            StatsSort::ResidentBytes => {
+                self.categories.sort_by_key(|c| std::cmp::Reverse(c.resident_bytes));
+                self.entries.sort_by_key(|e| std::cmp::Reverse(e.resident_bytes));
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn collect_stats<E: DatabaseEntry>(entries: &EntryMap<E>, category: AssetCategory, out: &mut Vec<EntryStats>) {
+    for entry in entries.values() {
+        if let Some(stats) = entry.load_stats() {
+            out.push(EntryStats {
+                category,
+                name: entry.name().to_string(),
+                loaded: entry.is_loaded(),
+                decode_ms: stats.decode_time.as_secs_f64() * 1000.0,
+                source_bytes: stats.source_bytes,
+                resident_bytes: stats.resident_bytes,
+            });
+        }
+    }
+}
+
+impl Database {
+    /// Stats of the last load of an entry. Shaders and custom entries are not
+    /// measured.
+    pub fn entry_load_stats(&self, key: &AssetKey) -> Option<LoadStats> {
+        match key.category {
+            AssetCategory::Image => self.images.get(&key.name)?.load_stats(),
+            AssetCategory::Atlas => self.atlases.get(&key.name)?.load_stats(),
+            AssetCategory::Geometry => self.geometry.get(&key.name)?.load_stats(),
+            AssetCategory::TTF => self.ttfs.get(&key.name)?.load_stats(),
+            AssetCategory::Particle => self.particles.get(&key.name)?.load_stats(),
+            AssetCategory::Audio => self.audio.get(&key.name)?.load_stats(),
+            AssetCategory::Shader | AssetCategory::Custom => None,
+        }
+    }
+
+    /// Load stats of every entry that has been loaded, with per category totals,
+    /// sorted by name.
+    pub fn load_stats(&self) -> StatsReport {
+        let mut entries = Vec::new();
+        collect_stats(&self.images, AssetCategory::Image, &mut entries);
+        collect_stats(&self.atlases, AssetCategory::Atlas, &mut entries);
+        collect_stats(&self.geometry, AssetCategory::Geometry, &mut entries);
+        collect_stats(&self.ttfs, AssetCategory::TTF, &mut entries);
+        collect_stats(&self.particles, AssetCategory::Particle, &mut entries);
+        collect_stats(&self.audio, AssetCategory::Audio, &mut entries);
+        StatsReport::from_entries(entries)
+    }
+}
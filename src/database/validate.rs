@@ -0,0 +1,547 @@
+use super::audio::*;
+use super::error::*;
+use super::format::*;
+use super::geometry::*;
+use super::json::*;
+use super::migrate::*;
+use super::render_graph::*;
+use super::vfs::*;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    /// A config file that could not be parsed.
+    InvalidConfig,
+    /// A config or asset file that does not exist.
+    MissingFile,
+    /// An asset file that exists but could not be decoded.
+    UnreadableFile,
+    DuplicateName,
+    SpriteOutOfBounds,
+    DuplicateSpriteId,
+    EmptyGlyphs,
+    /// A material texture that names no image or atlas entry.
+    UnresolvedTexture,
+    /// A render graph node whose subpass is not part of the render pass.
+    UnknownSubpass,
+    /// A render graph node using a mask missing from the `render_mask` table.
+    UndeclaredRenderMask,
+    /// An `execution_order` name that is neither a node nor `OUTPUT`.
+    UnknownNode,
+    /// An audio loop region that is empty or runs past the end of the clip.
+    InvalidLoopPoints,
+    /// A config key that no field of the config layout reads, usually a typo.
+    UnknownKey,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    /// Index of the layer the problem was found in.
+    pub layer: usize,
+    /// Config file the problem was found in.
+    pub config: String,
+    /// Entry the problem belongs to, if any.
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}: {}", self.config, entry, self.message),
+            None => write!(f, "{}: {}", self.config, self.message),
+        }
+    }
+}
+
+/// Every problem found in a database's configs and the files they reference.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    /// Layer that new issues are recorded against.
+    layer: usize,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn of_kind(&self, kind: IssueKind) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(move |i| i.kind == kind)
+    }
+
+    fn push(&mut self, kind: IssueKind, config: &str, entry: Option<&str>, message: String) {
+        self.issues.push(ValidationIssue {
+            kind,
+            layer: self.layer,
+            config: config.to_string(),
+            entry: entry.map(|e| e.to_string()),
+            message,
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_config<T: VersionedConfig>(
+    fs: &dyn FileSystem,
+    path: &str,
+    report: &mut ValidationReport,
+) -> Option<T> {
+    if !fs.exists(path) {
+        report.push(IssueKind::MissingFile, path, None, "config file does not exist".to_string());
+        return None;
+    }
+
+    let mut unknown = Vec::new();
+    let parsed = fs
+        .read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_versioned_checked(fs, path, &text, &mut Vec::new(), &mut unknown));
+    match parsed {
+        Ok(cfg) => {
+            for key in unknown {
+                report.push(IssueKind::UnknownKey, path, None, format!("unknown key {}", key));
+            }
+            Some(cfg)
+        }
+        Err(e) => {
+            report.push(IssueKind::InvalidConfig, path, None, e);
+            None
+        }
+    }
+}
+
+fn check_file(fs: &dyn FileSystem, config: &str, entry: &str, path: &str, report: &mut ValidationReport) -> bool {
+    if fs.exists(path) {
+        return true;
+    }
+
+    report.push(
+        IssueKind::MissingFile,
+        config,
+        Some(entry),
+        format!("file {} does not exist", path),
+    );
+    false
+}
+
+/// Checks that the file of every variant of `entry` exists.
+fn check_variant_files(fs: &dyn FileSystem, config: &str, entry: &dyn EntryConfig, report: &mut ValidationReport) {
+    for path in entry.variants().iter().filter_map(|v| v.path()) {
+        check_file(fs, config, entry.name(), path, report);
+    }
+}
+
+fn check_unique_names<'a>(config: &str, names: impl Iterator<Item = &'a str>, report: &mut ValidationReport) {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            report.push(
+                IssueKind::DuplicateName,
+                config,
+                Some(name),
+                "name is declared more than once".to_string(),
+            );
+        }
+    }
+}
+
+fn image_dimensions(fs: &dyn FileSystem, path: &str) -> Option<(u32, u32)> {
+    let bytes = fs.read(path).ok()?;
+    image::io::Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+fn check_bounds(
+    config: &str,
+    entry: &str,
+    what: &str,
+    bounds: &dashi::Rect2D,
+    size: (u32, u32),
+    report: &mut ValidationReport,
+) {
+    let right = bounds.x as u64 + bounds.w as u64;
+    let bottom = bounds.y as u64 + bounds.h as u64;
+    if right > size.0 as u64 || bottom > size.1 as u64 {
+        report.push(
+            IssueKind::SpriteOutOfBounds,
+            config,
+            Some(entry),
+            format!(
+                "{} ({}, {}, {}x{}) exceeds the {}x{} image",
+                what, bounds.x, bounds.y, bounds.w, bounds.h, size.0, size.1
+            ),
+        );
+    }
+}
+
+fn validate_atlases(fs: &dyn FileSystem, config: &str, cfg: &AtlasJSON, report: &mut ValidationReport) {
+    check_unique_names(config, cfg.atlases.iter().map(|a| a.name.as_str()), report);
+
+    for atlas in &cfg.atlases {
+        check_variant_files(fs, config, atlas, report);
+        let size = if check_file(fs, config, &atlas.name, &atlas.path, report) {
+            let size = image_dimensions(fs, &atlas.path);
+            if size.is_none() {
+                report.push(
+                    IssueKind::UnreadableFile,
+                    config,
+                    Some(&atlas.name),
+                    format!("{} is not a readable image", atlas.path),
+                );
+            }
+            size
+        } else {
+            None
+        };
+
+        let mut ids = HashSet::new();
+        for sprite in atlas.entries.iter().flatten() {
+            if !ids.insert(sprite.id) {
+                report.push(
+                    IssueKind::DuplicateSpriteId,
+                    config,
+                    Some(&atlas.name),
+                    format!("sprite id {} ({}) is used more than once", sprite.id, sprite.name),
+                );
+            }
+            if let Some(size) = size {
+                let what = format!("sprite {}", sprite.name);
+                check_bounds(config, &atlas.name, &what, &sprite.bounds, size, report);
+            }
+        }
+
+        if let (Some(auto_gen), Some(size)) = (&atlas.auto_gen, size) {
+            let what = format!("auto_gen {}", auto_gen.name);
+            check_bounds(config, &atlas.name, &what, &auto_gen.bounds, size, report);
+        }
+
+        for variant in atlas.variants.iter().flatten() {
+            check_atlas_variant(fs, config, atlas, variant, report);
+        }
+    }
+}
+
+/// Checks the sprites `variant` uses against its image. Variants without their own
+/// sprites use the base ones, which have to fit the variant image as well.
+fn check_atlas_variant(
+    fs: &dyn FileSystem,
+    config: &str,
+    atlas: &AtlasJSONEntry,
+    variant: &AtlasVariantJSON,
+    report: &mut ValidationReport,
+) {
+    // Missing variant files are reported by `check_variant_files`.
+    if !fs.exists(&variant.path) {
+        return;
+    }
+    let size = match image_dimensions(fs, &variant.path) {
+        Some(size) => size,
+        None => {
+            report.push(
+                IssueKind::UnreadableFile,
+                config,
+                Some(&atlas.name),
+                format!("{} is not a readable image", variant.path),
+            );
+            return;
+        }
+    };
+
+    let sprites = variant.entries.as_ref().or(atlas.entries.as_ref());
+    for sprite in sprites.into_iter().flatten() {
+        let what = format!("sprite {} of variant {}", sprite.name, variant.path);
+        check_bounds(config, &atlas.name, &what, &sprite.bounds, size, report);
+    }
+    if let Some(auto_gen) = &atlas.auto_gen {
+        let what = format!("auto_gen {} of variant {}", auto_gen.name, variant.path);
+        check_bounds(config, &atlas.name, &what, &auto_gen.bounds, size, report);
+    }
+}
+
+fn validate_particles(fs: &dyn FileSystem, config: &str, cfg: &ParticleJSON, report: &mut ValidationReport) {
+    check_unique_names(config, cfg.particles.iter().map(|p| p.name.as_str()), report);
+
+    for particle in &cfg.particles {
+        if !check_file(fs, config, &particle.name, &particle.image_path, report) {
+            continue;
+        }
+
+        let size = match image_dimensions(fs, &particle.image_path) {
+            Some(size) => size,
+            None => {
+                report.push(
+                    IssueKind::UnreadableFile,
+                    config,
+                    Some(&particle.name),
+                    format!("{} is not a readable image", particle.image_path),
+                );
+                continue;
+            }
+        };
+
+        for animation in &particle.animations {
+            for (frame, sprite) in animation.sprites.iter().enumerate() {
+                let what = format!("frame {} of animation {}", frame, animation.name);
+                check_bounds(config, &particle.name, &what, sprite, size, report);
+            }
+        }
+    }
+}
+
+fn validate_audio(fs: &dyn FileSystem, config: &str, cfg: &AudioJSON, report: &mut ValidationReport) {
+    check_unique_names(config, cfg.audio.iter().map(|a| a.name.as_str()), report);
+
+    for clip in &cfg.audio {
+        if !check_file(fs, config, &clip.name, &clip.path, report) {
+            continue;
+        }
+
+        let info = match probe_audio(fs, &clip.path) {
+            Ok(info) => info,
+            Err(e) => {
+                report.push(IssueKind::UnreadableFile, config, Some(&clip.name), e);
+                continue;
+            }
+        };
+
+        if let Some(points) = clip.loop_points {
+            let end = points.end.or(info.frames);
+            let past_end = matches!((points.end, info.frames), (Some(end), Some(frames)) if end > frames);
+            if end.map(|end| points.start >= end).unwrap_or(false) || past_end {
+                report.push(
+                    IssueKind::InvalidLoopPoints,
+                    config,
+                    Some(&clip.name),
+                    format!(
+                        "loop {}..{} does not fit the clip's {} frames",
+                        points.start,
+                        points.end.map(|e| e.to_string()).unwrap_or_default(),
+                        info.frames.map(|f| f.to_string()).unwrap_or_else(|| "?".to_string())
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn validate_models(
+    fs: &dyn FileSystem,
+    config: &str,
+    cfg: &GeometryJSON,
+    textures: &HashSet<String>,
+    report: &mut ValidationReport,
+) {
+    check_unique_names(config, cfg.models.iter().map(|m| m.name.as_str()), report);
+
+    for model in &cfg.models {
+        check_variant_files(fs, config, model, report);
+        if !check_file(fs, config, &model.name, &model.path, report) {
+            continue;
+        }
+
+        let loaded = match load_gltf_model(fs, &model.path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                report.push(
+                    IssueKind::UnreadableFile,
+                    config,
+                    Some(&model.name),
+                    format!("{} is not a readable glTF model: {}", model.path, error_cause(e)),
+                );
+                continue;
+            }
+        };
+
+        let mut reported = HashSet::new();
+        for mesh in &loaded.meshes {
+            for texture in mesh.material.textures.values() {
+                if !textures.contains(texture) && reported.insert(texture.clone()) {
+                    report.push(
+                        IssueKind::UnresolvedTexture,
+                        config,
+                        Some(&model.name),
+                        format!(
+                            "material {} references unknown texture {}",
+                            mesh.material.name, texture
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn validate_render_graph(config: &str, graph: &RenderGraph, report: &mut ValidationReport) {
+    check_unique_names(config, graph.nodes.iter().map(|n| n.name.as_str()), report);
+    check_unique_names(
+        config,
+        graph.render_pass.subpasses.iter().map(|s| s.name.as_str()),
+        report,
+    );
+
+    for node in &graph.nodes {
+        if let Some(subpass) = &node.subpass {
+            if graph.subpass(subpass).is_none() {
+                report.push(
+                    IssueKind::UnknownSubpass,
+                    config,
+                    Some(&node.name),
+                    format!("subpass {} is not part of the render pass", subpass),
+                );
+            }
+        }
+
+        for mask in &node.render_masks {
+            if graph.render_mask(mask).is_none() {
+                report.push(
+                    IssueKind::UndeclaredRenderMask,
+                    config,
+                    Some(&node.name),
+                    format!("render mask {} is not declared", mask),
+                );
+            }
+        }
+    }
+
+    for name in &graph.execution_order {
+        if name != OUTPUT_NODE && graph.node(name).is_none() {
+            report.push(
+                IssueKind::UnknownNode,
+                config,
+                None,
+                format!("execution_order references unknown node {}", name),
+            );
+        }
+    }
+}
+
+/// Checks the database rooted at `fs` without loading it, collecting every problem
+/// instead of stopping at the first.
+pub fn validate_filesystem(fs: &dyn FileSystem) -> ValidationReport {
+    validate_layers(&[fs])
+}
+
+/// Checks a stack of database layers, base first. Names only need to be unique
+/// within a layer, and material textures may refer to entries of any layer.
+pub fn validate_layers(layers: &[&dyn FileSystem]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    // Names material textures may refer to.
+    let mut textures = HashSet::new();
+    let mut models = Vec::new();
+
+    for (layer, fs) in layers.iter().copied().enumerate() {
+        report.layer = layer;
+        let root = match database_config(fs) {
+            Ok(root) => root,
+            Err(e) => {
+                report.push(IssueKind::InvalidConfig, DATABASE_CONFIGS[0], None, e);
+                continue;
+            }
+        };
+        let info: DatabaseJSON = match read_config(fs, root, &mut report) {
+            Some(info) => info,
+            None => continue,
+        };
+
+        if let Some(config) = &info.image_cfg {
+            if let Some(cfg) = read_config::<ImageJSON>(fs, config, &mut report) {
+                check_unique_names(config, cfg.images.iter().map(|i| i.name.as_str()), &mut report);
+                for image in &cfg.images {
+                    check_file(fs, config, &image.name, &image.path, &mut report);
+                    check_variant_files(fs, config, image, &mut report);
+                    textures.insert(image.name.clone());
+                }
+            }
+        }
+
+        if let Some(config) = &info.atlas_cfg {
+            if let Some(cfg) = read_config::<AtlasJSON>(fs, config, &mut report) {
+                validate_atlases(fs, config, &cfg, &mut report);
+                textures.extend(cfg.atlases.iter().map(|a| a.name.clone()));
+            }
+        }
+
+        if let Some(config) = &info.ttf_cfg {
+            if let Some(cfg) = read_config::<TTFJSON>(fs, config, &mut report) {
+                check_unique_names(config, cfg.fonts.iter().map(|f| f.name.as_str()), &mut report);
+                for font in &cfg.fonts {
+                    check_file(fs, config, &font.name, &font.path, &mut report);
+                    check_variant_files(fs, config, font, &mut report);
+                    if font.glyphs.as_ref().map(|g| g.is_empty()).unwrap_or(false) {
+                        report.push(
+                            IssueKind::EmptyGlyphs,
+                            config,
+                            Some(&font.name),
+                            "glyph string is empty".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Models are checked once every layer's textures are known.
+        if let Some(config) = &info.geometry_cfg {
+            if let Some(cfg) = read_config::<GeometryJSON>(fs, config, &mut report) {
+                models.push((layer, config.clone(), cfg));
+            }
+        }
+
+        if let Some(config) = &info.particle_cfg {
+            if let Some(cfg) = read_config::<ParticleJSON>(fs, config, &mut report) {
+                validate_particles(fs, config, &cfg, &mut report);
+            }
+        }
+
+        if let Some(config) = &info.audio_cfg {
+            if let Some(cfg) = read_config::<AudioJSON>(fs, config, &mut report) {
+                validate_audio(fs, config, &cfg, &mut report);
+            }
+        }
+
+        if let Some(config) = &info.localization_cfg {
+            if let Some(cfg) = read_config::<LocalizationJSON>(fs, config, &mut report) {
+                check_unique_names(config, cfg.locales.iter().map(|l| l.locale.as_str()), &mut report);
+            }
+        }
+
+        for config in info.categories.values() {
+            if let Some(cfg) = read_config::<CustomJSON>(fs, config, &mut report) {
+                check_unique_names(config, cfg.entries.iter().map(|e| e.name.as_str()), &mut report);
+                for entry in &cfg.entries {
+                    if let Some(path) = &entry.path {
+                        check_file(fs, config, &entry.name, path, &mut report);
+                    }
+                }
+            }
+        }
+
+        if let Some(config) = &info.render_graph_path {
+            if let Some(graph) = read_config::<RenderGraph>(fs, config, &mut report) {
+                validate_render_graph(config, &graph, &mut report);
+            }
+        }
+    }
+
+    for (layer, config, cfg) in models {
+        report.layer = layer;
+        validate_models(layers[layer], &config, &cfg, &textures, &mut report);
+    }
+
+    report
+}
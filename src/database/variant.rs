@@ -0,0 +1,91 @@
+use super::*;
+
+/// The traits of the machine the database serves, e.g. `["2x", "desktop"]`. Each
+/// image, atlas, font and model entry resolves to its variant that needs the most
+/// of these traits, or to its base config if no variant applies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VariantProfile {
+    traits: Vec<String>,
+}
+
+impl VariantProfile {
+    pub fn new(traits: &[&str]) -> Self {
+        Self {
+            traits: traits.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    pub fn traits(&self) -> &[String] {
+        &self.traits
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.traits.iter().any(|t| t == name)
+    }
+
+    /// Index of the variant that applies to this profile. Among variants whose
+    /// `when` traits are all present, the one with the most traits wins; ties go
+    /// to the one declared first.
+    pub fn select(&self, variants: &[&dyn VariantConfig]) -> Option<usize> {
+        let mut best: Option<(usize, usize)> = None;
+        for (index, variant) in variants.iter().enumerate() {
+            let when = variant.when();
+            if !when.iter().all(|t| self.has(t)) {
+                continue;
+            }
+            if best.map(|(_, len)| when.len() > len).unwrap_or(true) {
+                best = Some((index, when.len()));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+}
+
+/// Selects the variant of every entry for `profile`. Entries that switch variant
+/// are unloaded and reported in `changed`.
+pub fn select_variants<E: DatabaseEntry>(
+    entries: &mut EntryMap<E>,
+    profile: &VariantProfile,
+    category: AssetCategory,
+    changed: &mut Vec<AssetKey>,
+) {
+    for entry in entries.values_mut() {
+        let variant = profile.select(&entry.cfg().variants());
+        if variant == entry.variant() {
+            continue;
+        }
+
+        entry.unload();
+        entry.set_variant(variant);
+        changed.push(AssetKey::new(category, entry.name()));
+    }
+}
+
+impl Database {
+    pub fn variant_profile(&self) -> &VariantProfile {
+        &self.profile
+    }
+
+    /// Switches to `profile`. Entries whose variant changes are unloaded, and any
+    /// load in flight for them is dropped, so the next fetch loads the new variant.
+    /// Returns those entries.
+    pub fn set_variant_profile(&mut self, profile: VariantProfile) -> Vec<AssetKey> {
+        self.profile = profile;
+        self.select_variants()
+    }
+
+    /// Selects the variant of every entry for the active profile, e.g. after entries
+    /// were parsed or edited. Entries whose variant changed are unloaded and their
+    /// in-flight loads dropped. Returns those entries.
+    pub(crate) fn select_variants(&mut self) -> Vec<AssetKey> {
+        let mut changed = Vec::new();
+        select_variants(&mut self.images, &self.profile, AssetCategory::Image, &mut changed);
+        select_variants(&mut self.atlases, &self.profile, AssetCategory::Atlas, &mut changed);
+        select_variants(&mut self.geometry, &self.profile, AssetCategory::Geometry, &mut changed);
+        select_variants(&mut self.ttfs, &self.profile, AssetCategory::TTF, &mut changed);
+        for key in &changed {
+            self.forget_entry(key);
+        }
+        changed
+    }
+}
@@ -0,0 +1,458 @@
+use super::error::*;
+use super::pack::*;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// A file opened for reading, see `FileSystem::open`.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Read access to the files of a database. Paths are `/`-separated and relative
+/// to the root of the filesystem.
+pub trait FileSystem: Send + Sync {
+    /// Where the files come from, used in error messages.
+    fn root(&self) -> &str;
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error>;
+
+    fn exists(&self, path: &str) -> bool;
+
+    /// Modification time of `path`, if the filesystem can tell.
+    fn modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+
+    /// Size of `path` in bytes as stored, if it exists.
+    fn file_size(&self, path: &str) -> Option<u64> {
+        self.read(path).ok().map(|data| data.len() as u64)
+    }
+
+    /// Opens `path` for incremental reads. By default the whole file is read into
+    /// memory; files on disk are read as the reader advances.
+    fn open(&self, path: &str) -> Result<Box<dyn ReadSeek>, Error> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn read_to_string(&self, path: &str) -> Result<String, Error> {
+        String::from_utf8(self.read(path)?).map_err(|e| Error::from(e.to_string()))
+    }
+
+    /// Creates or replaces `path`. Read-only filesystems return an error.
+    fn write(&self, path: &str, _data: &[u8]) -> Result<(), Error> {
+        Err(Error::from(format!("cannot write {}: {} is read-only", path, self.root())))
+    }
+
+    /// Paths of every file below the directory `dir`, sorted. Filesystems that
+    /// can't enumerate their files return nothing.
+    fn list(&self, _dir: &str) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The sorted names out of `names` that lie below the directory `dir`.
+fn files_under<'a>(dir: &str, names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let dir = normalize_pack_name(dir).trim_end_matches('/');
+    let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+    let mut files: Vec<String> = names
+        .filter(|name| name.starts_with(&prefix) && !name.ends_with('/'))
+        .map(|name| name.to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Walks the directory `dir` on disk, adding the files below it as `path/name`.
+fn walk_directory(dir: &Path, path: &str, files: &mut Vec<String>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let child = if path.is_empty() { name } else { format!("{}/{}", path, name) };
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => walk_directory(&entry.path(), &child, files),
+            Ok(_) => files.push(child),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Splits `path` at the first `.zip/` whose prefix `is_archive` accepts, into the
+/// archive and the path inside it.
+fn split_archive(path: &str, is_archive: impl Fn(&str) -> bool) -> Option<(&str, &str)> {
+    let mut search = 0;
+    while let Some(idx) = path[search..].find(".zip/") {
+        let end = search + idx + ".zip".len();
+        if is_archive(&path[..end]) {
+            return Some((&path[..end], &path[end + 1..]));
+        }
+        search = end;
+    }
+    None
+}
+
+/// The files below `dir` inside `zip`, named by their path through `archive`.
+fn archive_files(archive: &str, zip: &ZipFs, dir: &str) -> Vec<String> {
+    zip.list(dir)
+        .into_iter()
+        .map(|name| format!("{}/{}", archive, name))
+        .collect()
+}
+
+pub(crate) fn not_found(root: &str, path: &str) -> Error {
+    Error::LoadingError(LoadingError {
+        entry: "[UNKNOWN]".to_string(),
+        path: format!("{}/{}", root, path),
+        cause: "file not found".to_string(),
+    })
+}
+
+/// Resolves `uri` relative to the directory holding `path`.
+pub fn sibling_path(path: &str, uri: &str) -> String {
+    match path.rfind('/') {
+        Some(idx) => format!("{}/{}", &path[..idx], uri),
+        None => uri.to_string(),
+    }
+}
+
+/// An open archive and the modification time it was opened at.
+type OpenArchive = (Option<SystemTime>, Arc<ZipFs>);
+
+/// Files in a directory on disk. Zip archives inside the directory can be read
+/// through transparently: `fonts/Roboto.zip/Roboto-Bold.ttf` reads
+/// `Roboto-Bold.ttf` out of `fonts/Roboto.zip`.
+pub struct DirectoryFs {
+    root: String,
+    archives: Mutex<HashMap<String, OpenArchive>>,
+}
+
+impl DirectoryFs {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            archives: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn full_path(&self, path: &str) -> String {
+        format!("{}/{}", self.root, path)
+    }
+
+    /// Splits `path` into an archive on disk and a path inside it.
+    fn split_archive<'a>(&self, path: &'a str) -> Option<(&'a str, &'a str)> {
+        split_archive(path, |archive| Path::new(&self.full_path(archive)).is_file())
+    }
+
+    /// Opens `archive`, reusing the open one unless the file changed since.
+    fn archive(&self, archive: &str) -> Result<Arc<ZipFs>, Error> {
+        let stamp = self.modified(archive);
+        let mut archives = self.archives.lock().unwrap();
+        match archives.get(archive) {
+            Some((opened, zip)) if *opened == stamp => return Ok(zip.clone()),
+            Some(_) => {
+                archives.remove(archive);
+            }
+            None => {}
+        }
+
+        let zip = Arc::new(ZipFs::open(&self.full_path(archive))?);
+        archives.insert(archive.to_string(), (stamp, zip.clone()));
+        Ok(zip)
+    }
+}
+
+impl FileSystem for DirectoryFs {
+    fn root(&self) -> &str {
+        &self.root
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let full = self.full_path(path);
+        if Path::new(&full).is_file() {
+            return Ok(std::fs::read(full)?);
+        }
+
+        match self.split_archive(path) {
+            Some((archive, inner)) => self.archive(archive)?.read(inner),
+            None => Err(not_found(&self.root, path)),
+        }
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ReadSeek>, Error> {
+        let full = self.full_path(path);
+        if Path::new(&full).is_file() {
+            return Ok(Box::new(std::fs::File::open(full)?));
+        }
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        if Path::new(&self.full_path(path)).is_file() {
+            return true;
+        }
+
+        match self.split_archive(path) {
+            Some((archive, inner)) => self
+                .archive(archive)
+                .map(|zip| zip.exists(inner))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    fn file_size(&self, path: &str) -> Option<u64> {
+        if let Ok(meta) = std::fs::metadata(self.full_path(path)) {
+            return Some(meta.len()).filter(|_| meta.is_file());
+        }
+
+        let (archive, inner) = self.split_archive(path)?;
+        self.archive(archive).ok()?.file_size(inner)
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        let target = match self.split_archive(path) {
+            Some((archive, _)) => archive,
+            None => path,
+        };
+
+        std::fs::metadata(self.full_path(target))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let full = self.full_path(path);
+        if let Some(parent) = Path::new(&full).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(std::fs::write(full, data)?)
+    }
+
+    /// Archives are listed as files. Listing an archive, or a directory inside
+    /// one, lists its contents.
+    fn list(&self, dir: &str) -> Vec<String> {
+        let dir = normalize_pack_name(dir).trim_end_matches('/');
+        let inside = format!("{}/", dir);
+        if let Some((archive, inner)) = self.split_archive(&inside) {
+            return self
+                .archive(archive)
+                .map(|zip| archive_files(archive, &zip, inner))
+                .unwrap_or_default();
+        }
+
+        let mut files = Vec::new();
+        walk_directory(Path::new(&self.full_path(dir)), dir, &mut files);
+        files.sort();
+        files
+    }
+}
+
+/// Files inside a zip archive. The archive is held in memory.
+pub struct ZipFs {
+    root: String,
+    archive: Mutex<zip::ZipArchive<Cursor<Vec<u8>>>>,
+}
+
+impl ZipFs {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        ZipFs::from_bytes(path, std::fs::read(path)?)
+    }
+
+    /// `name` identifies the archive in error messages.
+    pub fn from_bytes(name: &str, bytes: Vec<u8>) -> Result<Self, Error> {
+        let archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| {
+            Error::LoadingError(LoadingError {
+                entry: "[UNKNOWN]".to_string(),
+                path: name.to_string(),
+                cause: e.to_string(),
+            })
+        })?;
+
+        Ok(Self {
+            root: name.to_string(),
+            archive: Mutex::new(archive),
+        })
+    }
+}
+
+impl FileSystem for ZipFs {
+    fn root(&self) -> &str {
+        &self.root
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive
+            .by_name(normalize_pack_name(path))
+            .map_err(|_| not_found(&self.root, path))?;
+
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn file_size(&self, path: &str) -> Option<u64> {
+        let mut archive = self.archive.lock().unwrap();
+        let size = archive.by_name(normalize_pack_name(path)).ok()?.size();
+        Some(size)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let archive = self.archive.lock().unwrap();
+        let found = archive
+            .file_names()
+            .any(|name| name == normalize_pack_name(path));
+        found
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        let archive = self.archive.lock().unwrap();
+        files_under(dir, archive.file_names())
+    }
+}
+
+/// Files held in memory, mostly for tests and tools. Modification times come from
+/// a counter that every write advances rather than from the clock, so each write
+/// is seen as a change however quickly it follows the last.
+pub struct MemoryFs {
+    root: String,
+    files: RwLock<HashMap<String, (Vec<u8>, SystemTime)>>,
+    generation: AtomicU64,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self {
+            root: "[MEMORY]".to_string(),
+            files: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds or replaces a file. Replacing a file updates its modification time.
+    pub fn insert(&self, path: &str, data: impl Into<Vec<u8>>) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let stamp = SystemTime::UNIX_EPOCH + Duration::from_nanos(generation);
+        self.files
+            .write()
+            .unwrap()
+            .insert(normalize_pack_name(path).to_string(), (data.into(), stamp));
+    }
+
+    pub fn remove(&self, path: &str) {
+        self.files.write().unwrap().remove(normalize_pack_name(path));
+    }
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        MemoryFs::new()
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn root(&self) -> &str {
+        &self.root
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        match self.files.read().unwrap().get(normalize_pack_name(path)) {
+            Some((data, _)) => Ok(data.clone()),
+            None => Err(not_found(&self.root, path)),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files
+            .read()
+            .unwrap()
+            .contains_key(normalize_pack_name(path))
+    }
+
+    fn file_size(&self, path: &str) -> Option<u64> {
+        self.files
+            .read()
+            .unwrap()
+            .get(normalize_pack_name(path))
+            .map(|(data, _)| data.len() as u64)
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        self.files
+            .read()
+            .unwrap()
+            .get(normalize_pack_name(path))
+            .map(|(_, stamp)| *stamp)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.insert(path, data);
+        Ok(())
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        let files = self.files.read().unwrap();
+        files_under(dir, files.keys().map(|k| k.as_str()))
+    }
+}
+
+/// Zip archives in a pack are read through like with `DirectoryFs`.
+impl FileSystem for Pack {
+    fn root(&self) -> &str {
+        self.path()
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        if self.contains(path) {
+            return Pack::read(self, path);
+        }
+
+        match split_archive(path, |archive| self.contains(archive)) {
+            Some((archive, inner)) => self.archive(archive)?.read(inner),
+            None => Pack::read(self, path),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        if self.contains(path) {
+            return true;
+        }
+
+        match split_archive(path, |archive| self.contains(archive)) {
+            Some((archive, inner)) => self
+                .archive(archive)
+                .map(|zip| zip.exists(inner))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// The size before compression, so it matches the unpacked file.
+    fn file_size(&self, path: &str) -> Option<u64> {
+        if let Some(entry) = self.entry(path) {
+            return Some(entry.raw_length);
+        }
+
+        let (archive, inner) = split_archive(path, |archive| self.contains(archive))?;
+        self.archive(archive).ok()?.file_size(inner)
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        let inside = format!("{}/", normalize_pack_name(dir).trim_end_matches('/'));
+        if let Some((archive, inner)) = split_archive(&inside, |archive| self.contains(archive)) {
+            return self
+                .archive(archive)
+                .map(|zip| archive_files(archive, &zip, inner))
+                .unwrap_or_default();
+        }
+
+        files_under(dir, self.entries().map(|e| e.name.as_str()))
+    }
+}
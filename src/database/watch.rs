@@ -0,0 +1,190 @@
+use super::id::EntryMap;
+use super::images::*;
+use super::loader::*;
+use super::shaders::*;
+use super::vfs::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// An entry that was added, removed, re-parsed or invalidated by a reload.
+pub type ChangedAsset = AssetKey;
+
+/// Tracks file modification times. A file is reported as changed when its stamp
+/// differs from the one recorded on the previous check.
+#[derive(Default)]
+pub struct FileWatcher {
+    stamps: HashMap<String, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    pub fn watch(&mut self, path: &str, stamp: Option<SystemTime>) {
+        self.stamps.insert(path.to_string(), stamp);
+    }
+
+    /// Drops every watched path not in `paths`.
+    pub fn retain(&mut self, paths: &HashSet<String>) {
+        self.stamps.retain(|p, _| paths.contains(p));
+    }
+
+    /// Whether `stamp` differs from the one recorded for `path`, without recording
+    /// it. Paths seen for the first time are reported as unchanged.
+    pub fn is_changed(&self, path: &str, stamp: Option<SystemTime>) -> bool {
+        match self.stamps.get(path) {
+            Some(prev) => *prev != stamp,
+            None => false,
+        }
+    }
+
+    /// Records `stamp` for `path` and returns whether it differs from the previous
+    /// one. Paths seen for the first time are reported as unchanged.
+    pub fn changed(&mut self, path: &str, stamp: Option<SystemTime>) -> bool {
+        match self.stamps.insert(path.to_string(), stamp) {
+            Some(prev) => prev != stamp,
+            None => false,
+        }
+    }
+}
+
+/// Records the current stamp of an entry's source as its load starts, so that
+/// edits made while it loads or before the next poll are seen.
+pub fn watch_source(watchers: &mut [FileWatcher], layers: &[Arc<dyn FileSystem>], layer: usize, path: &str) {
+    let stamp = layers[layer].modified(path);
+    watchers[layer].watch(path, stamp);
+}
+
+/// Replaces `entries` with `fresh`, keeping the loaded data of every entry whose
+/// config and layer did not change.
+pub fn merge_entries<E: DatabaseEntry>(
+    entries: &mut EntryMap<E>,
+    mut fresh: EntryMap<E>,
+    category: AssetCategory,
+    changed: &mut Vec<ChangedAsset>,
+) {
+    for old in entries.values() {
+        if !fresh.contains_key(old.name()) {
+            changed.push(ChangedAsset::new(category, old.name()));
+        }
+    }
+
+    for entry in fresh.values_mut() {
+        let same = match entries.get(entry.name()) {
+            Some(old) => {
+                old.layer() == entry.layer()
+                    && serde_json::to_value(old.cfg()).ok() == serde_json::to_value(entry.cfg()).ok()
+            }
+            None => false,
+        };
+
+        if same {
+            let name = entry.name().to_string();
+            *entry = entries.remove(&name).unwrap();
+        } else {
+            changed.push(ChangedAsset::new(category, entry.name()));
+        }
+    }
+
+    *entries = fresh;
+}
+
+/// Replaces `shaders` with those just discovered. Shaders with the same layer and
+/// source files keep their compiled stages.
+pub fn merge_shaders(
+    shaders: &mut HashMap<String, ShaderEntry>,
+    mut fresh: HashMap<String, ShaderEntry>,
+    changed: &mut Vec<ChangedAsset>,
+) {
+    for name in shaders.keys().filter(|name| !fresh.contains_key(*name)) {
+        changed.push(ChangedAsset::new(AssetCategory::Shader, name));
+    }
+
+    for (name, shader) in fresh.iter_mut() {
+        match shaders.remove(name) {
+            Some(old) if old.layer == shader.layer && old.sources == shader.sources => shader.loaded = old.loaded,
+            _ => changed.push(ChangedAsset::new(AssetCategory::Shader, name)),
+        }
+    }
+
+    *shaders = fresh;
+}
+
+/// Layer and source path of every loaded entry.
+pub fn loaded_sources<E: DatabaseEntry>(
+    entries: &EntryMap<E>,
+    sources: &mut HashSet<(usize, String)>,
+) {
+    // Entries without a source file have an empty path.
+    for entry in entries.values().filter(|e| e.is_loaded() && !e.path().is_empty()) {
+        sources.insert((entry.layer(), entry.path().to_string()));
+    }
+}
+
+/// Layer and path of every source file of a compiled shader.
+pub fn shader_sources(shaders: &HashMap<String, ShaderEntry>, sources: &mut HashSet<(usize, String)>) {
+    for shader in shaders.values().filter(|s| s.is_loaded()) {
+        sources.extend(shader.sources.iter().map(|s| (shader.layer, s.path.clone())));
+    }
+}
+
+/// Layer and source path of every entry with a load in flight.
+pub fn pending_sources<E: DatabaseEntry, T>(
+    entries: &EntryMap<E>,
+    pending: &HashMap<String, LoadHandle<T>>,
+    sources: &mut HashSet<(usize, String)>,
+) {
+    for entry in pending.keys().filter_map(|name| entries.get(name)) {
+        sources.insert((entry.layer(), entry.path().to_string()));
+    }
+}
+
+/// Reports every load in flight whose source is in `stale`; it may have read the
+/// old file, so the caller drops it.
+pub fn stale_pending<E: DatabaseEntry, T>(
+    entries: &EntryMap<E>,
+    pending: &HashMap<String, LoadHandle<T>>,
+    stale: &HashSet<(usize, String)>,
+    category: AssetCategory,
+    changed: &mut Vec<ChangedAsset>,
+) {
+    for entry in pending.keys().filter_map(|name| entries.get(name)) {
+        if stale.contains(&(entry.layer(), entry.path().to_string())) {
+            changed.push(ChangedAsset {
+                category,
+                name: entry.name().to_string(),
+            });
+        }
+    }
+}
+
+/// Unloads every loaded entry whose source is in `stale`.
+pub fn invalidate_stale<E: DatabaseEntry>(
+    entries: &mut EntryMap<E>,
+    stale: &HashSet<(usize, String)>,
+    category: AssetCategory,
+    changed: &mut Vec<ChangedAsset>,
+) {
+    for entry in entries.values_mut().filter(|e| e.is_loaded()) {
+        if stale.contains(&(entry.layer(), entry.path().to_string())) {
+            entry.unload();
+            changed.push(ChangedAsset {
+                category,
+                name: entry.name().to_string(),
+            });
+        }
+    }
+}
+
+/// Drops the compiled stages of every shader with a source in `stale`, so the
+/// next fetch compiles it again.
+pub fn invalidate_stale_shaders(
+    shaders: &mut HashMap<String, ShaderEntry>,
+    stale: &HashSet<(usize, String)>,
+    changed: &mut Vec<ChangedAsset>,
+) {
+    for shader in shaders.values_mut().filter(|s| s.is_loaded()) {
+        if shader.sources.iter().any(|s| stale.contains(&(shader.layer, s.path.clone()))) {
+            shader.unload();
+            changed.push(ChangedAsset::new(AssetCategory::Shader, &shader.name));
+        }
+    }
+}